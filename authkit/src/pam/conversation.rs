@@ -16,6 +16,35 @@ use question::Question;
 mod answer;
 mod question;
 
+pub trait Conversation {
+    fn prompt(&self, question: &OsStr) -> Result<OsString>;
+
+    fn masked_prompt(&self, question: &OsStr) -> Result<OsString>;
+
+    fn info_msg(&self, message: &OsStr);
+
+    fn error_msg(&self, message: &OsStr);
+}
+
+fn communicate(conv: &dyn Conversation, messages: &[Exchange]) {
+    for msg in messages {
+        match msg {
+            Exchange::Prompt(prompt) => prompt.set_answer(conv.prompt(prompt.question())),
+            Exchange::MaskedPrompt(prompt) => {
+                prompt.set_answer(conv.masked_prompt(prompt.question()))
+            }
+            Exchange::Info(prompt) => {
+                conv.info_msg(prompt.question());
+                prompt.set_answer(Ok(()))
+            }
+            Exchange::Error(prompt) => {
+                conv.error_msg(prompt.question());
+                prompt.set_answer(Ok(()))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PamConversation {
     username: String,
@@ -29,51 +58,40 @@ impl PamConversation {
             password: password.into(),
         }
     }
+}
 
-    fn prompt(&self, _: impl AsRef<OsStr>) -> Result<OsString> {
+impl Conversation for PamConversation {
+    fn prompt(&self, _: &OsStr) -> Result<OsString> {
         Ok(OsString::from(&self.username))
     }
 
-    fn masked_prompt(&self, _: impl AsRef<OsStr>) -> Result<OsString> {
+    fn masked_prompt(&self, _: &OsStr) -> Result<OsString> {
         Ok(OsString::from(&self.password))
     }
 
-    fn error_msg(&self, _: impl AsRef<OsStr>) {}
-
-    fn info_msg(&self, _: impl AsRef<OsStr>) {}
+    fn info_msg(&self, _: &OsStr) {}
 
-    fn communicate(&self, messages: &[Exchange]) {
-        for msg in messages {
-            match msg {
-                Exchange::Prompt(prompt) => prompt.set_answer(self.prompt(prompt.question())),
-                Exchange::MaskedPrompt(prompt) => {
-                    prompt.set_answer(self.masked_prompt(prompt.question()))
-                }
-                Exchange::Info(prompt) => {
-                    self.info_msg(prompt.question());
-                    prompt.set_answer(Ok(()))
-                }
-                Exchange::Error(prompt) => {
-                    self.error_msg(prompt.question());
-                    prompt.set_answer(Ok(()))
-                }
-            }
-        }
-    }
+    fn error_msg(&self, _: &OsStr) {}
 }
 
 #[derive(Debug)]
 #[repr(C)]
 pub struct PamOwnedConversation {
     callback: pam::aliases::ConversationCallback,
-    conv: Box<PamConversation>,
+    conv: Box<Box<dyn Conversation>>,
+}
+
+impl fmt::Debug for dyn Conversation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("dyn Conversation")
+    }
 }
 
 impl PamOwnedConversation {
-    pub fn new(conv: PamConversation) -> Self {
+    pub fn new(conv: impl Conversation + 'static) -> Self {
         Self {
             callback: Self::wrapper_callback,
-            conv: Box::new(conv),
+            conv: Box::new(Box::new(conv)),
         }
     }
 
@@ -86,7 +104,7 @@ impl PamOwnedConversation {
         unsafe {
             let internal = || {
                 let conv = me
-                    .cast::<PamConversation>()
+                    .cast::<Box<dyn Conversation>>()
                     .as_ref()
                     .ok_or(ErrorCode::ConversationError)?;
                 let q_iter =
@@ -100,7 +118,7 @@ impl PamOwnedConversation {
 
                 let borrowed: Result<Vec<_>> = messages.iter().map(Exchange::try_from).collect();
 
-                conv.communicate(&borrowed?);
+                communicate(conv.as_ref(), &borrowed?);
 
                 let owned = Answers::build(messages)?;
                 *answers_ptr = owned.into_ptr();
@@ -1,6 +1,6 @@
 use crate::pam::constants;
 use crate::pam::constants::{ErrorCode, RawFlags, Result, ReturnCode};
-use crate::pam::conversation::{PamConversation, PamOwnedConversation};
+use crate::pam::conversation::{Conversation, PamConversation, PamOwnedConversation};
 use crate::pam::env::{PamEnv, PamEnvMut};
 use crate::pam::items::{PamItems, PamItemsMut};
 use crate::pam::{self, BaseFlags, CredAction};
@@ -31,12 +31,22 @@ impl fmt::Debug for Pam {
 
 impl Pam {
     pub fn start(service_name: OsString, username: OsString, password: OsString) -> Result<Self> {
-        let mut conv = Box::new(PamOwnedConversation::new(PamConversation::new(
+        let conv = PamConversation::new(
             username.to_str().expect("couldn't convert to string"),
             password.to_str().expect("couldn't convert to string"),
-        )));
+        );
+
+        Self::start_with(service_name, Some(username), conv)
+    }
+
+    pub fn start_with(
+        service_name: OsString,
+        username: Option<OsString>,
+        conversation: impl Conversation + 'static,
+    ) -> Result<Self> {
+        let mut conv = Box::new(PamOwnedConversation::new(conversation));
         let service_cstr = CString::new(service_name.as_bytes()).expect("null is forbidden");
-        let username_cstr = crate::pam::helper::option_cstr_os(Some(username).as_deref());
+        let username_cstr = crate::pam::helper::option_cstr_os(username.as_deref());
         let username_cstr = crate::pam::helper::prompt_ptr(username_cstr.as_deref());
 
         let mut handle: *mut pam::pam_handle = ptr::null_mut();
@@ -129,12 +139,12 @@ impl Pam {
         result
     }
 
-    pub fn end(&mut self, result: Result<()>) -> () {
+    pub fn end(&mut self, result: Result<()>) {
         let code: ReturnCode = result.into();
         unsafe { pam::pam_end(self.handle, code.into()) };
     }
 
-    pub fn end_silent(&mut self, result: Result<()>) -> () {
+    pub fn end_silent(&mut self, result: Result<()>) {
         let result: c_int = ReturnCode::from(result).into();
         let result = result | constants::PAM_DATA_SILENT;
         unsafe {
@@ -13,6 +13,7 @@ pub use {
     constants::{
         AuthnFlags, AuthtokAction, AuthtokFlags, BaseFlags, CredAction, ErrorCode, Result,
     },
+    conversation::{Conversation, PamConversation},
     env::{PamEnv, PamEnvMut},
     handle::Pam,
     items::{PamItems, PamItemsMut},
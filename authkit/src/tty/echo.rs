@@ -0,0 +1,29 @@
+use std::os::fd::BorrowedFd;
+
+use nix::errno::Errno;
+use nix::sys::termios::{self, LocalFlags, SetArg, Termios};
+
+/// Turns terminal echo off until dropped, for password input.
+pub struct NoEcho<'fd> {
+    fd: BorrowedFd<'fd>,
+    saved: Termios,
+}
+
+impl<'fd> NoEcho<'fd> {
+    pub fn new(fd: BorrowedFd<'fd>) -> Result<Self, Errno> {
+        let saved = termios::tcgetattr(fd)?;
+
+        let mut silent = saved.clone();
+        silent.local_flags.remove(LocalFlags::ECHO);
+        silent.local_flags.insert(LocalFlags::ECHONL);
+        termios::tcsetattr(fd, SetArg::TCSAFLUSH, &silent)?;
+
+        Ok(Self { fd, saved })
+    }
+}
+
+impl Drop for NoEcho<'_> {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(self.fd, SetArg::TCSANOW, &self.saved);
+    }
+}
@@ -0,0 +1,94 @@
+mod echo;
+mod pty;
+mod vt;
+
+use std::io::{Read, Write};
+use std::os::fd::AsFd;
+
+use nix::errno::Errno;
+
+pub use {
+    echo::NoEcho,
+    pty::Pty,
+    vt::{TTY, Vt, close, current, open, switch, take},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Mode {
+    Text,
+    Graphics,
+}
+
+/// Operations rilm needs from a virtual terminal.
+///
+/// [`Vt`] drives a real `/dev/ttyN`, [`Pty`] simulates one on top of a
+/// pseudo-terminal so the same code can run unprivileged.
+pub trait Terminal: AsFd + Read + Write {
+    /// The VT number this terminal stands for.
+    fn number(&self) -> u16;
+
+    /// The VT currently shown on the seat.
+    fn active(&self) -> Result<u16, Errno>;
+
+    /// Switch the seat to `vt` and wait until the switch is done.
+    fn activate(&mut self, vt: u16) -> Result<(), Errno>;
+
+    fn mode(&self) -> Result<Mode, Errno>;
+
+    fn set_mode(&mut self, mode: Mode) -> Result<(), Errno>;
+
+    /// Make this terminal the controlling terminal of the calling process.
+    fn take_control(&mut self) -> Result<(), Errno>;
+}
+
+/// A terminal brought to the foreground, remembering what to restore.
+#[derive(Debug)]
+pub struct Claim<T: Terminal> {
+    terminal: T,
+    previous_vt: u16,
+    previous_mode: Mode,
+}
+
+impl<T: Terminal> Claim<T> {
+    pub fn new(mut terminal: T) -> Result<Self, Errno> {
+        let previous_vt = terminal.active()?;
+        let previous_mode = terminal.mode()?;
+
+        if previous_vt != terminal.number() {
+            terminal.activate(terminal.number())?;
+        }
+
+        terminal.take_control()?;
+
+        Ok(Self {
+            terminal,
+            previous_vt,
+            previous_mode,
+        })
+    }
+
+    pub fn terminal(&self) -> &T {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut T {
+        &mut self.terminal
+    }
+
+    pub fn previous_vt(&self) -> u16 {
+        self.previous_vt
+    }
+
+    /// Put back the mode and the VT that were active before the claim.
+    pub fn release(mut self) -> Result<T, Errno> {
+        if self.terminal.mode()? != self.previous_mode {
+            self.terminal.set_mode(self.previous_mode)?;
+        }
+
+        if self.terminal.active()? != self.previous_vt {
+            self.terminal.activate(self.previous_vt)?;
+        }
+
+        Ok(self.terminal)
+    }
+}
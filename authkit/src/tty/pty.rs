@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+
+use nix::errno::Errno;
+
+use crate::tty::{Mode, Terminal};
+
+/// A pseudo-terminal standing in for a VT.
+///
+/// Reads and writes go to the slave side, like they would on `/dev/ttyN`,
+/// while the master side is left to whoever plays the user. The VT number,
+/// the active VT, the mode and the controlling state are only simulated.
+#[derive(Debug)]
+pub struct Pty {
+    master: File,
+    slave: File,
+    number: u16,
+    active: u16,
+    mode: Mode,
+    controlling: bool,
+}
+
+impl Pty {
+    pub fn open(number: u16, active: u16) -> Result<Self, Errno> {
+        let pty = nix::pty::openpty(None, None)?;
+
+        Ok(Self {
+            master: File::from(pty.master),
+            slave: File::from(pty.slave),
+            number,
+            active,
+            mode: Mode::Text,
+            controlling: false,
+        })
+    }
+
+    pub fn master(&mut self) -> &mut File {
+        &mut self.master
+    }
+
+    pub fn is_controlling(&self) -> bool {
+        self.controlling
+    }
+}
+
+impl Terminal for Pty {
+    fn number(&self) -> u16 {
+        self.number
+    }
+
+    fn active(&self) -> Result<u16, Errno> {
+        Ok(self.active)
+    }
+
+    fn activate(&mut self, vt: u16) -> Result<(), Errno> {
+        if vt == 0 {
+            return Err(Errno::ENXIO);
+        }
+
+        self.active = vt;
+        Ok(())
+    }
+
+    fn mode(&self) -> Result<Mode, Errno> {
+        Ok(self.mode)
+    }
+
+    fn set_mode(&mut self, mode: Mode) -> Result<(), Errno> {
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn take_control(&mut self) -> Result<(), Errno> {
+        self.controlling = true;
+        Ok(())
+    }
+}
+
+impl AsFd for Pty {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.slave.as_fd()
+    }
+}
+
+impl Read for Pty {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.slave.read(buf)
+    }
+}
+
+impl Write for Pty {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.slave.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.slave.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tty::{Claim, NoEcho};
+
+    #[test]
+    fn claim_switches_and_restores() {
+        let pty = Pty::open(3, 1).unwrap();
+
+        let mut claim = Claim::new(pty).unwrap();
+        assert_eq!(claim.previous_vt(), 1);
+        assert_eq!(claim.terminal().active(), Ok(3));
+        assert!(claim.terminal().is_controlling());
+
+        claim.terminal_mut().set_mode(Mode::Graphics).unwrap();
+
+        let pty = claim.release().unwrap();
+        assert_eq!(pty.active(), Ok(1));
+        assert_eq!(pty.mode(), Ok(Mode::Text));
+    }
+
+    #[test]
+    fn claim_on_active_vt_stays() {
+        let pty = Pty::open(2, 2).unwrap();
+
+        let claim = Claim::new(pty).unwrap();
+        assert_eq!(claim.terminal().active(), Ok(2));
+
+        let pty = claim.release().unwrap();
+        assert_eq!(pty.active(), Ok(2));
+    }
+
+    #[test]
+    fn terminal_io_reaches_master() {
+        let mut pty = Pty::open(1, 1).unwrap();
+
+        pty.write_all(b"login: ").unwrap();
+        pty.flush().unwrap();
+
+        let mut buf = [0u8; 7];
+        pty.master().read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"login: ");
+
+        pty.master().write_all(b"enzo\n").unwrap();
+
+        let mut buf = [0u8; 5];
+        pty.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"enzo\n");
+    }
+
+    #[test]
+    fn no_echo_hides_input() {
+        let mut pty = Pty::open(1, 1).unwrap();
+        let slave = pty.slave.try_clone().unwrap();
+
+        let guard = NoEcho::new(slave.as_fd()).unwrap();
+        pty.master().write_all(b"secret\n").unwrap();
+
+        let mut buf = [0u8; 7];
+        pty.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"secret\n");
+
+        let mut echo = [0u8; 2];
+        pty.master().read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, b"\r\n");
+
+        drop(guard);
+    }
+}
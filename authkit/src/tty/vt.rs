@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, IntoRawFd, OwnedFd};
+
+use nix::errno::Errno;
+
+use crate::tty::{Mode, Terminal};
+
+pub type TTY = OwnedFd;
+
+const VT_GETSTATE: nix::sys::ioctl::ioctl_num_type = 0x5603;
+const VT_WAITACTIVE: nix::sys::ioctl::ioctl_num_type = 0x5607;
+const VT_SETACTIVATE: nix::sys::ioctl::ioctl_num_type = 0x560F;
+const KDSETMODE: nix::sys::ioctl::ioctl_num_type = 0x4B3A;
+const KDGETMODE: nix::sys::ioctl::ioctl_num_type = 0x4B3B;
+const TIOCSCTTY: nix::sys::ioctl::ioctl_num_type = 0x540E;
+
+const KD_TEXT: i32 = 0x00;
+const KD_GRAPHICS: i32 = 0x01;
+
+pub fn open(vt: u16) -> Result<TTY, Errno> {
+    let fd = nix::fcntl::open(
+        format!("/dev/tty{}", vt).as_str(),
+        nix::fcntl::OFlag::O_RDWR | nix::fcntl::OFlag::O_NOCTTY,
+        nix::sys::stat::Mode::from_bits_truncate(0o666),
+    )?;
+
+    Ok(fd)
+}
+
+pub fn close(tty: TTY) {
+    unsafe {
+        libc::close(tty.into_raw_fd());
+    }
+}
+
+pub fn current(fd: &TTY) -> u16 {
+    get_active(fd.as_fd()).unwrap_or_default()
+}
+
+pub fn switch(fd: &TTY, vt: u16) {
+    let _ = set_active(fd.as_fd(), vt);
+}
+
+pub fn take(fd: &TTY) {
+    let _ = set_controlling(fd.as_fd());
+}
+
+fn get_active(fd: BorrowedFd) -> Result<u16, Errno> {
+    #[allow(dead_code, non_camel_case_types)]
+    #[repr(C)]
+    struct vt_state {
+        pub v_active: u16,
+        pub v_signal: u16,
+        pub v_state: u16,
+    }
+    let mut state = vt_state {
+        v_active: 0,
+        v_signal: 0,
+        v_state: 0,
+    };
+
+    Errno::result(unsafe { nix::libc::ioctl(fd.as_raw_fd(), VT_GETSTATE, &mut state) })?;
+
+    Ok(state.v_active)
+}
+
+fn set_active(fd: BorrowedFd, vt: u16) -> Result<(), Errno> {
+    #[allow(dead_code, non_camel_case_types)]
+    #[repr(C)]
+    struct vt_mode {
+        pub mode: u8,
+        pub waitv: u8,
+        pub relsig: u16,
+        pub acqsig: u16,
+        pub frsig: u16,
+    }
+
+    #[allow(dead_code, non_camel_case_types)]
+    #[repr(C)]
+    struct vt_setactivate {
+        pub console: u32,
+        pub mode: vt_mode,
+    }
+
+    let setactivate = vt_setactivate {
+        console: vt as u32,
+        mode: vt_mode {
+            mode: 0,
+            waitv: 0,
+            relsig: 0,
+            acqsig: 0,
+            frsig: 0,
+        },
+    };
+
+    Errno::result(unsafe { nix::libc::ioctl(fd.as_raw_fd(), VT_SETACTIVATE, &setactivate) })?;
+
+    loop {
+        match Errno::result(unsafe {
+            nix::libc::ioctl(fd.as_raw_fd(), VT_WAITACTIVE, vt as nix::libc::c_ulong)
+        }) {
+            Err(Errno::EINTR) => continue,
+            result => return result.map(drop),
+        }
+    }
+}
+
+fn set_controlling(fd: BorrowedFd) -> Result<(), Errno> {
+    Errno::result(unsafe { nix::libc::ioctl(fd.as_raw_fd(), TIOCSCTTY, 1) }).map(drop)
+}
+
+/// A real Linux virtual terminal, `/dev/ttyN`. Needs root.
+#[derive(Debug)]
+pub struct Vt {
+    file: File,
+    number: u16,
+}
+
+impl Vt {
+    pub fn open(number: u16) -> Result<Self, Errno> {
+        Ok(Self {
+            file: File::from(open(number)?),
+            number,
+        })
+    }
+}
+
+impl Terminal for Vt {
+    fn number(&self) -> u16 {
+        self.number
+    }
+
+    fn active(&self) -> Result<u16, Errno> {
+        get_active(self.file.as_fd())
+    }
+
+    fn activate(&mut self, vt: u16) -> Result<(), Errno> {
+        set_active(self.file.as_fd(), vt)
+    }
+
+    fn mode(&self) -> Result<Mode, Errno> {
+        let mut mode: nix::libc::c_int = KD_TEXT;
+
+        Errno::result(unsafe { nix::libc::ioctl(self.file.as_raw_fd(), KDGETMODE, &mut mode) })?;
+
+        Ok(match mode {
+            KD_GRAPHICS => Mode::Graphics,
+            _ => Mode::Text,
+        })
+    }
+
+    fn set_mode(&mut self, mode: Mode) -> Result<(), Errno> {
+        let mode = match mode {
+            Mode::Text => KD_TEXT,
+            Mode::Graphics => KD_GRAPHICS,
+        };
+
+        Errno::result(unsafe { nix::libc::ioctl(self.file.as_raw_fd(), KDSETMODE, mode) }).map(drop)
+    }
+
+    fn take_control(&mut self) -> Result<(), Errno> {
+        set_controlling(self.file.as_fd())
+    }
+}
+
+impl AsFd for Vt {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
+
+impl Read for Vt {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for Vt {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,14 @@
+use authkit::{AuthnFlags, Conversation, Pam};
+
+pub fn authenticate(
+    service: &str,
+    user: &str,
+    conversation: impl Conversation + 'static,
+) -> authkit::Result<Pam> {
+    let mut txn = Pam::start_with(service.into(), Some(user.into()), conversation)?;
+
+    txn.authenticate(AuthnFlags::empty())?;
+    txn.account_management(AuthnFlags::empty())?;
+
+    Ok(txn)
+}
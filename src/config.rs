@@ -1,117 +1,551 @@
-pub const NIRI_GREETER_CONFIG: &'static str = r##"
-spawn-at-startup "swaybg" "-i" "/usr/share/backgrounds/f43/default/f43-01-day.jxl";
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
-hotkey-overlay {
-skip-at-startup
+use nix::sys::resource::Resource;
+use serde::{Deserialize, Serialize};
+
+use crate::{log, niri};
+
+use super::{Error, Result};
+
+pub const CONFIG_PATH: &str = "/etc/rilm/config.toml";
+pub const CONFIG_DROPINS: &str = "/etc/rilm/config.d";
+
+/// rilm's configuration, every field falls back to the compiled-in default.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub display: Display,
+    pub greeter: Greeter,
+    pub session: Session,
+    pub autologin: Autologin,
+    pub xwayland: Xwayland,
+    pub accounting: Accounting,
+    pub log: Log,
+    pub programs: Programs,
+    pub niri: Niri,
 }
 
-input {
-    keyboard { xkb { layout "fr"; variant "mac"; }; }
-    touchpad { tap; natural-scroll; dwt; }
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Display {
+    /// VT used when none is given on the command line.
+    pub tty: u16,
+    pub wallpaper: PathBuf,
 }
 
-output "eDP-1" { mode "1920x1080@60.042"; }
+impl Default for Display {
+    fn default() -> Self {
+        Self {
+            tty: 1,
+            wallpaper: PathBuf::from("/usr/share/backgrounds/f43/default/f43-01-day.jxl"),
+        }
+    }
+}
 
-layout {
-    gaps 10
-    struts { left -6; right -6; top -6; bottom -6; }
-    center-focused-column "never"
-    focus-ring { width 4; active-gradient from="#efc05d" to="#161d43" angle=45 relative-to="workspace-view"; }
-    border { off; }
-    shadow { on; }
-    default-column-width { proportion 1.0; }
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Greeter {
+    pub user: String,
+    pub groups: Vec<String>,
+    pub home: PathBuf,
+    pub pam_service: String,
+    /// Greeter program to run instead of the built-in prompt.
+    pub command: Vec<String>,
+    /// niri configuration to use instead of the built-in one.
+    pub niri_config: Option<PathBuf>,
+    /// Pre-fill the last user, session and layout, off for shared machines.
+    pub remember: bool,
 }
 
-window-rule { geometry-corner-radius 10; clip-to-geometry true; min-width 300; min-height 300; }
+impl Default for Greeter {
+    fn default() -> Self {
+        Self {
+            user: String::from("greeter"),
+            groups: ["video", "input", "render"].map(String::from).to_vec(),
+            home: PathBuf::from("/var/lib/rilm-greeter"),
+            pam_service: String::from("rilm-greeter"),
+            command: Vec::new(),
+            niri_config: None,
+            remember: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Session {
+    pub pam_service: String,
+    /// What the built-in niri session runs in its terminal.
+    pub command: Vec<String>,
+    pub niri_config: Option<PathBuf>,
+    pub user_niri_config: UserNiriConfig,
+    pub limits: Limits,
+    /// Seconds what a session left running gets to exit after logout,
+    /// before it is killed.
+    pub stop_timeout: u64,
+    /// PATH before PAM and the login shell add to it.
+    pub path: String,
+    /// Start sessions through `$SHELL -l -c`, so that /etc/profile and
+    /// ~/.profile apply.
+    pub login_shell: bool,
+    pub systemd: Systemd,
+}
 
-binds {
-    Mod+Shift+E { quit; }
+/// Resource limits of sessions as `[soft, hard]`, inherited when unset.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Limits {
+    pub nofile: Option<[u64; 2]>,
+    pub core: Option<[u64; 2]>,
+    pub memlock: Option<[u64; 2]>,
 }
-"##;
 
-pub const NIRI_SESSION_CONFIG: &'static str = r##"
-prefer-no-csd
-screenshot-path "/home/enzo/Pictures/Screenshots/Screenshot from %Y-%m-%d %H-%M-%S.png"
+impl Limits {
+    pub fn resources(&self) -> Vec<(Resource, [u64; 2])> {
+        [
+            (Resource::RLIMIT_NOFILE, self.nofile),
+            (Resource::RLIMIT_CORE, self.core),
+            (Resource::RLIMIT_MEMLOCK, self.memlock),
+        ]
+        .into_iter()
+        .filter_map(|(resource, limit)| Some((resource, limit?)))
+        .collect()
+    }
+}
 
-spawn-at-startup "swaybg" "-i" "/usr/share/backgrounds/f43/default/f43-01-day.jxl";
+/// How sessions involve the user's systemd instance.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Systemd {
+    /// Leave it alone.
+    #[default]
+    Off,
+    /// Share the session environment and keep graphical-session.target
+    /// active while the session runs.
+    Target,
+    /// Run the built-in session as niri.service, like niri-session. Other
+    /// sessions get `target`.
+    Service,
+}
 
-input {
-    keyboard { xkb { layout "fr"; variant "mac"; }; }
-    touchpad { tap; natural-scroll; dwt; }
+/// What the built-in session does with `~/.config/niri/config.kdl` when the
+/// user has one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserNiriConfig {
+    /// Include it, then add rilm's binds and startup programs.
+    #[default]
+    Overlay,
+    AsIs,
+    Ignore,
 }
 
-output "eDP-1" { mode "1920x1080@60.042"; }
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            pam_service: String::from("rilm"),
+            command: vec![String::from("/usr/bin/nu")],
+            niri_config: None,
+            user_niri_config: UserNiriConfig::default(),
+            limits: Limits::default(),
+            stop_timeout: 5,
+            path: String::from("/usr/local/sbin:/usr/local/bin:/usr/bin"),
+            login_shell: false,
+            systemd: Systemd::default(),
+        }
+    }
+}
 
-layout {
-    gaps 10
-    struts { left -6; right -6; top -6; bottom -6; }
-    center-focused-column "never"
-    focus-ring { width 4; active-gradient from="#efc05d" to="#161d43" angle=45 relative-to="workspace-view"; }
-    border { off; }
-    shadow { on; }
-    default-column-width { proportion 1.0; }
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Log {
+    pub level: log::Level,
+    /// Log to journald when it runs, to `dir` otherwise.
+    pub journal: bool,
+    pub dir: PathBuf,
+    /// Greeter and session output kept per user in ~/.local/state/rilm,
+    /// 0 to leave it on the console.
+    pub keep_sessions: usize,
 }
 
-window-rule { geometry-corner-radius 10; clip-to-geometry true; min-width 300; min-height 300; }
-window-rule { match app-id=r#"firefox$"# title="^Picture-in-Picture$"; open-floating true; }
-window-rule { match app-id=r#"Alacritty$"#; default-column-width { proportion 0.5; }; }
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            level: log::Level::Info,
+            journal: true,
+            dir: PathBuf::from("/var/log/rilm"),
+            keep_sessions: 5,
+        }
+    }
+}
 
-binds {
-    Mod+Shift+D { show-hotkey-overlay; }
+/// xwayland-satellite run alongside sessions, for X11 programs.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Xwayland {
+    /// Off by default, recent niri versions start it on their own.
+    pub enable: bool,
+    /// Per user, over `enable`.
+    pub users: BTreeMap<String, bool>,
+    /// Per session id (`rilm` for the built-in one), over everything else.
+    pub sessions: BTreeMap<String, bool>,
+    /// Crashes within a minute before it is left down.
+    pub max_restarts: usize,
+}
 
-    Mod+Left  { focus-column-or-monitor-left; }
-    Mod+Down  { focus-window-or-workspace-down; }
-    Mod+Up    { focus-window-or-workspace-up; }
-    Mod+Right { focus-column-or-monitor-right; }
+impl Default for Xwayland {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            users: BTreeMap::new(),
+            sessions: BTreeMap::new(),
+            max_restarts: 5,
+        }
+    }
+}
 
-    Mod+Shift+Down  { move-window-down-or-to-workspace-down; }
-    Mod+Shift+Up    { move-window-up-or-to-workspace-up; }
-    Mod+Shift+Left  { consume-or-expel-window-left; }
-    Mod+Shift+Right { consume-or-expel-window-right; }
+impl Xwayland {
+    pub fn enabled(&self, user: &str, session: &str) -> bool {
+        self.sessions
+            .get(session)
+            .or_else(|| self.users.get(user))
+            .copied()
+            .unwrap_or(self.enable)
+    }
+}
 
-    Mod+ampersand { focus-workspace 1; }
-    Mod+eacute { focus-workspace 2; }
-    Mod+quotedbl { focus-workspace 3; }
-    Mod+apostrophe { focus-workspace 4; }
-    Mod+parenleft { focus-workspace 5; }
-    Mod+minus { focus-workspace 6; }
-    Mod+egrave { focus-workspace 7; }
-    Mod+underscore { focus-workspace 8; }
-    Mod+ccedilla { focus-workspace 9; }
+/// Login records, for `who`, `last` and `lastlog`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Accounting {
+    pub enable: bool,
+    pub utmp: PathBuf,
+    pub wtmp: PathBuf,
+    /// Written through `programs.lastlog2` when it exists.
+    pub lastlog2: PathBuf,
+}
 
-    Mod+Shift+ampersand { move-window-to-workspace 1; }
-    Mod+Shift+eacute { move-window-to-workspace 2; }
-    Mod+Shift+quotedbl { move-window-to-workspace 3; }
-    Mod+Shift+apostrophe { move-window-to-workspace 4; }
-    Mod+Shift+parenleft { move-window-to-workspace 5; }
-    Mod+Shift+minus { move-window-to-workspace 6; }
-    Mod+Shift+egrave { move-window-to-workspace 7; }
-    Mod+Shift+underscore { move-window-to-workspace 8; }
-    Mod+Shift+ccedilla { move-window-to-workspace 9; }
+impl Default for Accounting {
+    fn default() -> Self {
+        Self {
+            enable: true,
+            utmp: PathBuf::from("/run/utmp"),
+            wtmp: PathBuf::from("/var/log/wtmp"),
+            lastlog2: PathBuf::from("/var/lib/lastlog/lastlog2.db"),
+        }
+    }
+}
 
-    Alt+Ctrl+Left { set-window-width "-10%"; }
-    Alt+Ctrl+Right { set-window-width "+10%"; }
-    Alt+Ctrl+Up { set-window-height "-10%"; }
-    Alt+Ctrl+Down { set-window-height "+10%"; }
+/// Open a user's session without going through the greeter.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Autologin {
+    /// Autologin is off when unset.
+    pub user: Option<String>,
+    pub pam_service: String,
+    /// Desktop session id, like `sway` for `sway.desktop`, the built-in niri
+    /// session when unset.
+    pub session: Option<String>,
+    /// Only autologin once per boot, the greeter shows after logout.
+    pub once: bool,
+    /// Seconds during which a key press on the VT shows the greeter instead.
+    pub delay: u64,
+}
 
-    XF86AudioRaiseVolume    allow-when-locked=true { spawn "wpctl" "set-volume" "@DEFAULT_AUDIO_SINK@" "0.1+"; }
-    XF86AudioLowerVolume    allow-when-locked=true { spawn "wpctl" "set-volume" "@DEFAULT_AUDIO_SINK@" "0.1-"; }
-    XF86AudioMute           allow-when-locked=true { spawn "wpctl" "set-mute" "@DEFAULT_AUDIO_SINK@" "toggle"; }
-    XF86AudioMicMute        allow-when-locked=true { spawn "wpctl" "set-mute" "@DEFAULT_AUDIO_SOURCE@" "toggle"; }
-    XF86MonBrightnessUp     allow-when-locked=true { spawn "brightnessctl" "s" "10%+"; }
-    XF86MonBrightnessDown   allow-when-locked=true { spawn "brightnessctl" "s" "10%-"; }
+impl Default for Autologin {
+    fn default() -> Self {
+        Self {
+            user: None,
+            pam_service: String::from("rilm-autologin"),
+            session: None,
+            once: false,
+            delay: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Programs {
+    pub niri: PathBuf,
+    /// Terminal the built-in greeter and session run in, with the flag that
+    /// precedes the command.
+    pub terminal: Vec<String>,
+    pub startx: PathBuf,
+    pub systemctl: PathBuf,
+    pub xwayland_satellite: PathBuf,
+    pub lastlog2: PathBuf,
+}
+
+impl Default for Programs {
+    fn default() -> Self {
+        Self {
+            niri: PathBuf::from("/usr/bin/niri"),
+            terminal: vec![String::from("/usr/bin/alacritty"), String::from("-e")],
+            startx: PathBuf::from("/usr/bin/startx"),
+            systemctl: PathBuf::from("/usr/bin/systemctl"),
+            xwayland_satellite: PathBuf::from("/usr/bin/xwayland-satellite"),
+            lastlog2: PathBuf::from("/usr/bin/lastlog2"),
+        }
+    }
+}
+
+/// What goes into the generated niri configurations.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Niri {
+    pub keyboard: Keyboard,
+    pub touchpad: Touchpad,
+    pub outputs: Vec<Output>,
+    /// Keys bound to workspaces 1, 2, ... with Mod, and Mod+Shift to move
+    /// windows there.
+    pub workspace_keys: Vec<String>,
+    pub terminal: String,
+    pub launcher: String,
+}
+
+impl Default for Niri {
+    fn default() -> Self {
+        Self {
+            keyboard: Keyboard::default(),
+            touchpad: Touchpad::default(),
+            outputs: Vec::new(),
+            workspace_keys: (1..=9).map(|i| i.to_string()).collect(),
+            terminal: String::from("alacritty"),
+            launcher: String::from("fuzzel"),
+        }
+    }
+}
+
+/// Unset fields follow the system keymap.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Keyboard {
+    pub layout: Option<String>,
+    pub variant: Option<String>,
+    pub options: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Touchpad {
+    pub tap: bool,
+    pub natural_scroll: bool,
+    pub dwt: bool,
+}
+
+impl Default for Touchpad {
+    fn default() -> Self {
+        Self {
+            tap: true,
+            natural_scroll: true,
+            dwt: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Output {
+    pub name: String,
+    pub mode: Option<String>,
+    pub scale: Option<f64>,
+}
 
-    Mod+R { maximize-column; }
-    Mod+F { fullscreen-window; }
+impl Config {
+    /// `/etc/rilm/config.toml` then every `/etc/rilm/config.d/*.toml` in
+    /// lexical order, later files overriding earlier ones key by key.
+    pub fn load() -> Result<Self> {
+        Self::load_from(Path::new(CONFIG_PATH), Path::new(CONFIG_DROPINS))
+    }
 
-    Mod+Shift+A { close-window; }
-    Mod+Return { spawn "alacritty"; }
-    Mod+D { spawn "fuzzel"; }
-    Mod+Shift+E { quit; }
-    Mod+Shift+P { power-off-monitors; }
-
-    Print { screenshot; }
-    Ctrl+Print { screenshot-screen; }
-    Alt+Print { screenshot-window; }
-}
-"##;
+    pub fn load_from(path: &Path, dropins: &Path) -> Result<Self> {
+        let mut files = vec![path.to_path_buf()];
+
+        if let Ok(entries) = std::fs::read_dir(dropins) {
+            let mut found = entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                .collect::<Vec<_>>();
+            found.sort();
+            files.extend(found);
+        }
+
+        let mut merged = toml::Table::new();
+
+        for file in files {
+            let contents = match std::fs::read_to_string(&file) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::Config(format!("{}: {e}", file.display()))),
+            };
+
+            // Each file must be valid on its own, so that errors point into it.
+            let error = |e: toml::de::Error| Error::Config(format!("{}: {e}", file.display()));
+            toml::from_str::<Config>(&contents).map_err(error)?;
+
+            merge(&mut merged, toml::from_str(&contents).map_err(error)?);
+        }
+
+        Config::deserialize(merged).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    pub fn dump(&self) -> Result<String> {
+        toml::to_string_pretty(self).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    pub fn greeter_niri_config(&self) -> Result<String> {
+        match &self.greeter.niri_config {
+            Some(path) => Ok(std::fs::read_to_string(path)?),
+            None => Ok(niri::Config::greeter(self).to_kdl()),
+        }
+    }
+
+    /// The user's own niri config comes first, then the configured one, then
+    /// the built-in session.
+    pub fn session_niri_config(&self, home: Option<&Path>) -> Result<String> {
+        let user = home.map(niri::user_config).filter(|path| path.is_file());
+
+        match (user, &self.session.niri_config) {
+            (Some(user), _) if self.session.user_niri_config == UserNiriConfig::Overlay => {
+                Ok(niri::overlay(self, &user))
+            }
+            (Some(user), _) if self.session.user_niri_config == UserNiriConfig::AsIs => {
+                Ok(std::fs::read_to_string(user)?)
+            }
+            (_, Some(path)) => Ok(std::fs::read_to_string(path)?),
+            (_, None) => Ok(niri::Config::session(self, home).to_kdl()),
+        }
+    }
+
+    /// Seed the niri config of the user whose home is `home` with the
+    /// built-in session, returning where it was written.
+    pub fn init_user(&self, home: &Path, force: bool) -> Result<PathBuf> {
+        let path = niri::user_config(home);
+
+        if !force && path.exists() {
+            return Err(Error::Config(format!(
+                "{} already exists, use --force to replace it",
+                path.display()
+            )));
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, niri::Config::session(self, Some(home)).to_kdl())?;
+
+        Ok(path)
+    }
+}
+
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => drop(base.insert(key, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropins_override_key_by_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let dropins = dir.path().join("config.d");
+        std::fs::create_dir(&dropins).unwrap();
+
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "[display]\ntty = 2\n\n[greeter]\nuser = \"kiosk\"\n",
+        )
+        .unwrap();
+        std::fs::write(dropins.join("10-tty.toml"), "display.tty = 3\n").unwrap();
+        std::fs::write(
+            dropins.join("20-session.toml"),
+            "[session]\ncommand = [\"/usr/bin/fish\"]\n",
+        )
+        .unwrap();
+        std::fs::write(dropins.join("ignored.conf"), "not toml").unwrap();
+
+        let config = Config::load_from(&dir.path().join("config.toml"), &dropins).unwrap();
+
+        assert_eq!(config.display.tty, 3);
+        assert_eq!(config.greeter.user, "kiosk");
+        assert_eq!(config.greeter.pam_service, "rilm-greeter");
+        assert_eq!(config.session.command, ["/usr/bin/fish"]);
+    }
+
+    #[test]
+    fn errors_point_to_the_bad_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[display]\ntty = 1\nwallpaper = 3\n").unwrap();
+
+        let Err(Error::Config(message)) = Config::load_from(&path, dir.path()) else {
+            panic!("config should be refused");
+        };
+
+        assert!(message.starts_with(&format!("{}: ", path.display())));
+        assert!(message.contains("line 3"));
+    }
+
+    #[test]
+    fn user_niri_config_is_seeded_and_used() {
+        let home = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+
+        let path = config.init_user(home.path(), false).unwrap();
+        assert!(config.init_user(home.path(), false).is_err());
+        std::fs::write(&path, "// mine\n").unwrap();
+
+        let overlay = config.session_niri_config(Some(home.path())).unwrap();
+        assert!(overlay.starts_with(&format!("include \"{}\"\n", path.display())));
+        assert!(overlay.contains("Super+Alt+L"));
+
+        config.session.user_niri_config = UserNiriConfig::AsIs;
+        assert_eq!(
+            config.session_niri_config(Some(home.path())).unwrap(),
+            "// mine\n"
+        );
+
+        config.session.user_niri_config = UserNiriConfig::Ignore;
+        assert_eq!(
+            config.session_niri_config(Some(home.path())).unwrap(),
+            niri::Config::session(&config, Some(home.path())).to_kdl()
+        );
+    }
+
+    #[test]
+    fn xwayland_sessions_override_users() {
+        let config: Config = toml::from_str(
+            "[xwayland]\n\
+             enable = true\n\
+             users = { erin = false }\n\
+             sessions = { rilm = true, sway = false }\n",
+        )
+        .unwrap();
+
+        assert!(config.xwayland.enabled("alice", "niri"));
+        assert!(!config.xwayland.enabled("erin", "niri"));
+        assert!(config.xwayland.enabled("erin", "rilm"));
+        assert!(!config.xwayland.enabled("alice", "sway"));
+    }
+
+    #[test]
+    fn dump_round_trips() {
+        let config = Config::default();
+        assert_eq!(
+            toml::from_str::<Config>(&config.dump().unwrap()).unwrap(),
+            config
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd, OwnedFd},
+        unix::ffi::{OsStrExt, OsStringExt},
+    },
+};
+
+use authkit::{
+    Conversation, ErrorCode, Pam,
+    tty::{NoEcho, Terminal},
+};
+
+use nix::{
+    errno::Errno,
+    poll::{PollFd, PollFlags, PollTimeout},
+    sys::termios::{self, LocalFlags, SetArg},
+};
+
+use crate::{auth, notify};
+
+use super::Result;
+
+const ISSUE_PATH: &str = "/etc/issue";
+const OS_RELEASE_PATH: &str = "/etc/os-release";
+
+pub enum Outcome {
+    Authenticated { user: String, txn: Pam },
+    RetryGraphical,
+}
+
+/// Text-mode login running straight on the VT, for when the compositor
+/// can't be started. Gives up as if the login was left empty once
+/// `interrupt` is readable.
+pub fn run(terminal: &impl Terminal, service: &str, interrupt: BorrowedFd) -> Result<Outcome> {
+    let file = File::from(terminal.as_fd().try_clone_to_owned()?);
+    let mut out = &file;
+
+    let issue = std::fs::read_to_string(ISSUE_PATH).unwrap_or_default();
+
+    out.write_all(b"\x1b[H\x1b[2J")?;
+    out.write_all(expand_issue(&issue, terminal.number()).as_bytes())?;
+    out.write_all(b"The graphical greeter failed to start.\n")?;
+    out.write_all(b"Leave the login empty to retry it.\n\n")?;
+
+    loop {
+        out.write_all(b"login: ")?;
+
+        let Some(login) = read_line(&file, interrupt)? else {
+            return Ok(Outcome::RetryGraphical);
+        };
+
+        let login = String::from_utf8_lossy(&login).trim().to_string();
+        if login.is_empty() {
+            return Ok(Outcome::RetryGraphical);
+        }
+
+        let conversation = Console {
+            file: file.try_clone()?,
+            interrupt: interrupt.try_clone_to_owned()?,
+        };
+
+        match auth::authenticate(service, &login, conversation) {
+            Ok(txn) => return Ok(Outcome::Authenticated { user: login, txn }),
+            Err(e) => out.write_all(format!("\nLogin incorrect ({e})\n\n").as_bytes())?,
+        }
+    }
+}
+
+/// Count `delay` seconds down before logging `user` in, `false` if a key was
+/// pressed to get the greeter instead, or `interrupt` became readable.
+pub fn countdown(
+    terminal: &impl Terminal,
+    user: &str,
+    delay: u64,
+    interrupt: BorrowedFd,
+) -> Result<bool> {
+    let file = File::from(terminal.as_fd().try_clone_to_owned()?);
+    let mut out = &file;
+
+    let saved = termios::tcgetattr(&file)?;
+    let mut raw = saved.clone();
+    raw.local_flags
+        .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+    termios::tcsetattr(&file, SetArg::TCSAFLUSH, &raw)?;
+
+    let pressed = (|| -> Result<bool> {
+        for left in (1..=delay).rev() {
+            out.write_all(
+                format!("\r\x1b[KLogging in as {user} in {left}s, press any key for the greeter")
+                    .as_bytes(),
+            )?;
+
+            let mut fds = [
+                PollFd::new(file.as_fd(), PollFlags::POLLIN),
+                PollFd::new(interrupt, PollFlags::POLLIN),
+            ];
+            notify::ping();
+            match nix::poll::poll(&mut fds, PollTimeout::from(1000u16)) {
+                Ok(0) | Err(Errno::EINTR) => continue,
+                Ok(_) => return Ok(true),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(false)
+    })();
+
+    // Flushing drops the key that was pressed.
+    termios::tcsetattr(&file, SetArg::TCSAFLUSH, &saved)?;
+    out.write_all(b"\n")?;
+
+    Ok(!pressed?)
+}
+
+/// `None` at the end of input or once `interrupt` is readable.
+fn read_line(mut file: &File, interrupt: BorrowedFd) -> std::io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+
+    loop {
+        let mut fds = [
+            PollFd::new(file.as_fd(), PollFlags::POLLIN),
+            PollFd::new(interrupt, PollFlags::POLLIN),
+        ];
+        let ready = match nix::poll::poll(&mut fds, notify::timeout()) {
+            Err(Errno::EINTR) => continue,
+            ready => ready?,
+        };
+        notify::ping();
+
+        if ready == 0 {
+            continue;
+        }
+        if fds[1].any().unwrap_or_default() {
+            return Ok(None);
+        }
+
+        match file.read(&mut byte) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(0) => return Ok(Some(line)),
+            Ok(_) if byte[0] == b'\n' => return Ok(Some(line)),
+            Ok(_) => line.push(byte[0]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+struct Console {
+    file: File,
+    interrupt: OwnedFd,
+}
+
+impl Console {
+    fn ask(&self, question: &OsStr, masked: bool) -> authkit::Result<OsString> {
+        (&self.file)
+            .write_all(question.as_bytes())
+            .map_err(|_| ErrorCode::ConversationError)?;
+
+        let _echo = match masked {
+            true => Some(NoEcho::new(self.file.as_fd()).map_err(|_| ErrorCode::ConversationError)?),
+            false => None,
+        };
+
+        read_line(&self.file, self.interrupt.as_fd())
+            .ok()
+            .flatten()
+            .map(OsString::from_vec)
+            .ok_or(ErrorCode::ConversationError)
+    }
+
+    fn say(&self, message: &OsStr) {
+        let mut file = &self.file;
+        let _ = file.write_all(message.as_bytes());
+        let _ = file.write_all(b"\n");
+    }
+}
+
+impl Conversation for Console {
+    fn prompt(&self, question: &OsStr) -> authkit::Result<OsString> {
+        self.ask(question, false)
+    }
+
+    fn masked_prompt(&self, question: &OsStr) -> authkit::Result<OsString> {
+        self.ask(question, true)
+    }
+
+    fn info_msg(&self, message: &OsStr) {
+        self.say(message)
+    }
+
+    fn error_msg(&self, message: &OsStr) {
+        self.say(message)
+    }
+}
+
+/// Expand the agetty escapes rilm knows about, drop the others.
+fn expand_issue(issue: &str, tty: u16) -> String {
+    let uts = nix::sys::utsname::uname().ok();
+    let uts = |f: fn(&nix::sys::utsname::UtsName) -> &OsStr| {
+        uts.as_ref()
+            .map(|uts| f(uts).to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    let mut out = String::with_capacity(issue.len());
+    let mut chars = issue.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => out.push_str(&uts(|u| u.nodename())),
+            Some('s') => out.push_str(&uts(|u| u.sysname())),
+            Some('r') => out.push_str(&uts(|u| u.release())),
+            Some('v') => out.push_str(&uts(|u| u.version())),
+            Some('m') => out.push_str(&uts(|u| u.machine())),
+            Some('l') => out.push_str(&format!("tty{tty}")),
+            Some('S') => {
+                let mut key = String::from("PRETTY_NAME");
+                if chars.peek() == Some(&'{') {
+                    chars.next();
+                    key = chars.by_ref().take_while(|&c| c != '}').collect();
+                }
+                out.push_str(&os_release(&key).unwrap_or_default());
+            }
+            Some('\\') => out.push('\\'),
+            _ => {}
+        }
+    }
+
+    out
+}
+
+fn os_release(key: &str) -> Option<String> {
+    std::fs::read_to_string(OS_RELEASE_PATH)
+        .ok()?
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, v)| v.trim().trim_matches('"').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use authkit::tty::Pty;
+
+    use super::*;
+
+    fn screen(pty: &mut Pty, until: &str) -> String {
+        let mut screen = String::new();
+        let mut buf = [0; 1024];
+
+        while !screen.contains(until) {
+            let len = pty.master().read(&mut buf).unwrap();
+            screen.push_str(&String::from_utf8_lossy(&buf[..len]));
+        }
+
+        screen
+    }
+
+    #[test]
+    fn empty_login_retries_graphical() {
+        let mut pty = Pty::open(2, 2).unwrap();
+        let (interrupt, _sender) = UnixStream::pair().unwrap();
+
+        pty.master().write_all(b"\n").unwrap();
+
+        let outcome = run(&pty, "rilm", interrupt.as_fd()).unwrap();
+        assert!(matches!(outcome, Outcome::RetryGraphical));
+        assert!(screen(&mut pty, "login: ").contains("The graphical greeter failed to start."));
+    }
+
+    #[test]
+    fn interrupt_gives_up_on_the_login() {
+        let pty = Pty::open(2, 2).unwrap();
+        let (interrupt, mut sender) = UnixStream::pair().unwrap();
+
+        sender.write_all(b"stop").unwrap();
+
+        let outcome = run(&pty, "rilm", interrupt.as_fd()).unwrap();
+        assert!(matches!(outcome, Outcome::RetryGraphical));
+    }
+
+    #[test]
+    fn countdown_is_cancelled_by_a_key() {
+        let mut pty = Pty::open(2, 2).unwrap();
+        let (interrupt, _sender) = UnixStream::pair().unwrap();
+
+        // Input from before the countdown is flushed, press the key during it.
+        let mut master = pty.master().try_clone().unwrap();
+        let press = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            master.write_all(b"x").unwrap();
+        });
+        assert!(!countdown(&pty, "erin", 5, interrupt.as_fd()).unwrap());
+        press.join().unwrap();
+
+        assert!(countdown(&pty, "erin", 1, interrupt.as_fd()).unwrap());
+        assert!(screen(&mut pty, "in 1s").contains("Logging in as erin in 5s"));
+    }
+}
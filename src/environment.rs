@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    fs::OpenOptions,
+    io::{self, ErrorKind, Read},
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use nix::{
+    libc,
+    unistd::{Uid, User},
+};
+
+use crate::{config, log};
+
+use super::Result;
+
+pub const LOCALE_CONF: &str = "/etc/locale.conf";
+/// Applied after environment.d, for variables only rilm sessions get.
+pub const RILM_ENVIRONMENT: &str = "/etc/rilm/environment";
+/// System environment.d directories, lowest priority first. The user's
+/// `~/.config/environment.d` comes last.
+pub const ENVIRONMENT_D: [&str; 4] = [
+    "/usr/lib/environment.d",
+    "/usr/local/lib/environment.d",
+    "/run/environment.d",
+    "/etc/environment.d",
+];
+
+/// Variables in the order they were first set, later values replacing
+/// earlier ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Environment {
+    vars: Vec<(String, String)>,
+}
+
+impl Environment {
+    /// This process' environment, leaving out what isn't UTF-8.
+    pub fn inherited() -> Self {
+        Self {
+            vars: std::env::vars_os()
+                .filter_map(|(key, val)| Some((key.into_string().ok()?, val.into_string().ok()?)))
+                .collect(),
+        }
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        let (key, value) = (key.into(), value.into());
+
+        match self.vars.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.vars.push((key, value)),
+        }
+    }
+
+    /// Set `key` only when nothing set it before.
+    pub fn set_default(&mut self, key: &str, value: impl Into<String>) {
+        if self.get(key).is_none() {
+            self.set(key, value);
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.vars
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set every `KEY=VALUE` of `vars`, skipping anything else.
+    pub fn extend(&mut self, vars: &[String]) {
+        for (key, value) in vars.iter().filter_map(|var| var.split_once('=')) {
+            self.set(key, value);
+        }
+    }
+
+    pub fn strings(&self) -> Vec<String> {
+        self.vars
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect()
+    }
+
+    pub fn cstrings(&self) -> Result<Vec<CString>> {
+        self.strings()
+            .into_iter()
+            .map(|var| Ok(CString::new(var)?))
+            .collect()
+    }
+}
+
+/// What `user` logs in with: the configured PATH and the system locale,
+/// then `base` (what PAM or the caller set up) and the passwd entry, then
+/// environment.d and [`RILM_ENVIRONMENT`].
+pub fn login(user: &User, base: &[String], session: &config::Session) -> Environment {
+    let mut env = Environment::default();
+
+    env.set("PATH", &session.path);
+    if let Ok(contents) = std::fs::read_to_string(LOCALE_CONF) {
+        env.extend(&locale(&contents));
+    }
+
+    env.extend(base);
+
+    env.set("HOME", user.dir.to_string_lossy());
+    env.set("SHELL", user.shell.to_string_lossy());
+    env.set("USER", &user.name);
+    env.set("LOGNAME", &user.name);
+
+    env.set_default("XDG_SESSION_TYPE", "wayland");
+    env.set_default("XDG_CURRENT_DESKTOP", "niri");
+
+    // pam_systemd sets it, without it fall back to where logind puts it.
+    let runtime_dir = format!("/run/user/{}", user.uid);
+    if Path::new(&runtime_dir).is_dir() {
+        env.set_default("XDG_RUNTIME_DIR", runtime_dir);
+    }
+
+    let own = user.dir.join(".config/environment.d");
+    let mut dirs = ENVIRONMENT_D.map(PathBuf::from).to_vec();
+    dirs.push(own.clone());
+
+    for path in environment_d(&dirs)
+        .into_iter()
+        .chain([PathBuf::from(RILM_ENVIRONMENT)])
+    {
+        let contents = match path.starts_with(&own) {
+            true => read_owned(&path, user.uid),
+            false => std::fs::read_to_string(&path),
+        };
+
+        match contents {
+            Ok(contents) => assign(&mut env, &contents, &path),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => log::warning!("Ignoring {}: {e}", path.display()),
+        }
+    }
+
+    env
+}
+
+/// A file of the user's own environment.d, read as root: it must be theirs
+/// and not a symlink, so that it can't hand over what only root may read.
+fn read_owned(path: &Path, uid: Uid) -> io::Result<String> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)?;
+
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.uid() != uid.as_raw() {
+        return Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            "not a file of the user",
+        ));
+    }
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    Ok(contents)
+}
+
+/// The `*.conf` files of `dirs` sorted by name, a file hiding those with
+/// the same name in the directories before it, as systemd does.
+pub fn environment_d(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = BTreeMap::new();
+
+    for dir in dirs {
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "conf") {
+                files.insert(entry.file_name(), path);
+            }
+        }
+    }
+
+    files.into_values().collect()
+}
+
+/// Apply the `KEY=VALUE` lines of `contents`, read from `path`, in order,
+/// each value expanded against what is set so far. Bad lines are only
+/// pointed at, never logged: the file may not be what it claims to be.
+pub fn assign(env: &mut Environment, contents: &str, path: &Path) {
+    for (number, line) in contents.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            log::warning!(
+                "{}:{}: Ignoring line without '='",
+                path.display(),
+                number + 1
+            );
+            continue;
+        };
+
+        let key = key.trim();
+        if !is_name(key) {
+            log::warning!(
+                "{}:{}: Ignoring invalid variable name",
+                path.display(),
+                number + 1
+            );
+            continue;
+        }
+
+        let value = unquote(value.trim());
+        let value = expand(value, env);
+        env.set(key, value);
+    }
+}
+
+/// `$VAR`, `${VAR}`, `${VAR:-default}` and `${VAR:+alternate}`, with `\$`
+/// for a literal dollar. Unset variables expand to nothing.
+pub fn expand(value: &str, env: &Environment) -> String {
+    let mut expanded = String::new();
+    let mut rest = value;
+
+    while let Some(at) = rest.find(['$', '\\']) {
+        expanded.push_str(&rest[..at]);
+        rest = &rest[at..];
+
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            let mut chars = escaped.chars();
+            expanded.extend(chars.next());
+            rest = chars.as_str();
+            continue;
+        }
+
+        rest = &rest[1..];
+
+        if let Some(braced) = rest.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            let inner = &braced[..end];
+            rest = &braced[end + 1..];
+
+            if let Some((name, default)) = inner.split_once(":-") {
+                match env.get(name).filter(|value| !value.is_empty()) {
+                    Some(value) => expanded.push_str(value),
+                    None => expanded.push_str(default),
+                }
+            } else if let Some((name, alternate)) = inner.split_once(":+") {
+                if env.get(name).is_some_and(|value| !value.is_empty()) {
+                    expanded.push_str(alternate);
+                }
+            } else {
+                expanded.push_str(env.get(inner).unwrap_or_default());
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+
+            if end == 0 {
+                expanded.push('$');
+            } else {
+                expanded.push_str(env.get(&rest[..end]).unwrap_or_default());
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+fn is_name(key: &str) -> bool {
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .iter()
+        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(value)
+}
+
+/// The `LANG`, `LANGUAGE` and `LC_*` assignments of a locale.conf, unquoted.
+pub fn locale(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| *key == "LANG" || *key == "LANGUAGE" || key.starts_with("LC_"))
+        .map(|(key, value)| format!("{key}={}", unquote(value.trim())))
+        .collect()
+}
+
+/// Run `command` through `shell` as a login shell, so that /etc/profile and
+/// ~/.profile apply to it.
+pub fn login_shell(shell: &Path, command: &[String]) -> Vec<String> {
+    let quoted = command
+        .iter()
+        .map(|arg| format!("'{}'", arg.replace('\'', r"'\''")))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    vec![
+        shell.to_string_lossy().into_owned(),
+        String::from("-l"),
+        String::from("-c"),
+        format!("exec {quoted}"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_values_replace_earlier_ones() {
+        let mut env = Environment::default();
+        env.set("PATH", "/bin");
+        env.extend(&[String::from("LANG=C"), String::from("PATH=/usr/bin")]);
+        env.set_default("LANG", "en_US.UTF-8");
+
+        assert_eq!(env.strings(), ["PATH=/usr/bin", "LANG=C"]);
+    }
+
+    #[test]
+    fn reads_locale_conf() {
+        let contents = "# comment\nLANG=\"fr_FR.UTF-8\"\nLC_TIME='en_GB.UTF-8'\nKEYMAP=fr\n";
+
+        assert_eq!(
+            locale(contents),
+            ["LANG=fr_FR.UTF-8", "LC_TIME=en_GB.UTF-8"]
+        );
+    }
+
+    #[test]
+    fn expands_like_systemd() {
+        let mut env = Environment::default();
+        env.set("HOME", "/home/erin");
+        env.set("EMPTY", "");
+
+        assign(
+            &mut env,
+            "# comment\n\
+             PATH=$HOME/.local/bin:${PATH:-/usr/bin}\n\
+             EDITOR=${EDITOR:-vi}\n\
+             QT=${EMPTY:+set}${HOME:+wayland}\n\
+             PRICE=\"\\$5 $\"\n\
+             1BAD=x\n",
+            Path::new("test.conf"),
+        );
+
+        assert_eq!(env.get("PATH"), Some("/home/erin/.local/bin:/usr/bin"));
+        assert_eq!(env.get("EDITOR"), Some("vi"));
+        assert_eq!(env.get("QT"), Some("wayland"));
+        assert_eq!(env.get("PRICE"), Some("$5 $"));
+        assert_eq!(env.get("1BAD"), None);
+    }
+
+    #[test]
+    fn later_directories_hide_files_with_the_same_name() {
+        let (system, user) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+
+        for (dir, name) in [
+            (&system, "10-a.conf"),
+            (&system, "20-b.conf"),
+            (&system, "ignored.txt"),
+            (&user, "20-b.conf"),
+            (&user, "05-c.conf"),
+        ] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let dirs = [system.path().to_path_buf(), user.path().to_path_buf()];
+
+        assert_eq!(
+            environment_d(&dirs),
+            [
+                user.path().join("05-c.conf"),
+                system.path().join("10-a.conf"),
+                user.path().join("20-b.conf"),
+            ]
+        );
+    }
+
+    #[test]
+    fn user_files_must_be_theirs_and_not_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (file, link) = (dir.path().join("10-a.conf"), dir.path().join("20-b.conf"));
+        std::fs::write(&file, "A=1\n").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        let uid = Uid::current();
+        assert_eq!(read_owned(&file, uid).unwrap(), "A=1\n");
+        assert_eq!(
+            read_owned(&file, Uid::from_raw(uid.as_raw() + 1))
+                .unwrap_err()
+                .kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert!(read_owned(&link, uid).is_err());
+    }
+
+    #[test]
+    fn quotes_commands_for_the_login_shell() {
+        let command = [String::from("/usr/bin/rilm"), String::from("it's")];
+
+        assert_eq!(
+            login_shell(Path::new("/bin/bash"), &command),
+            ["/bin/bash", "-l", "-c", r"exec '/usr/bin/rilm' 'it'\''s'"]
+        );
+    }
+}
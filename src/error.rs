@@ -3,9 +3,19 @@ use std::ffi::NulError;
 use nix::errno::Errno;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     UnknownCurrentUserHost,
     UnknownUserWithName(String),
+    MissingEnv(&'static str),
+    Protocol(String),
+    Install(String),
+    Account(String),
+    UnknownProgram(String),
+    Config(String),
+    Spawn(String),
+    Systemd(String),
+    Accounting(String),
     NulError(NulError),
     UserError(Errno),
     IoError(std::io::Error),
@@ -20,6 +30,15 @@ impl core::fmt::Display for Error {
             Self::UnknownUserWithName(name) => {
                 write!(f, "RILM was provided an unknown username {name}.")
             }
+            Self::MissingEnv(var) => write!(f, "RILM expected {var} to be set."),
+            Self::Protocol(e) => write!(f, "Greeter protocol error: {e}"),
+            Self::Install(e) => write!(f, "Couldn't patch the configuration: {e}"),
+            Self::Account(e) => write!(f, "System account error: {e}"),
+            Self::UnknownProgram(name) => write!(f, "RILM couldn't find the program {name}."),
+            Self::Config(e) => write!(f, "Invalid configuration: {e}"),
+            Self::Spawn(e) => write!(f, "Couldn't start {e}"),
+            Self::Systemd(e) => write!(f, "systemd user manager error: {e}"),
+            Self::Accounting(e) => write!(f, "Login accounting error: {e}"),
             Self::NulError(e) => write!(f, "{e}"),
             Self::UserError(e) => write!(f, "{e}"),
             Self::IoError(e) => write!(f, "{e}"),
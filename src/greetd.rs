@@ -0,0 +1,330 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::net::UnixStream,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ipc::{Codec, Request, Response};
+
+use super::{Error, Result};
+
+pub const SOCKET_ENV: &str = "GREETD_SOCK";
+
+const MAX_PAYLOAD_LEN: usize = 64 * 1024;
+/// What greetd runs before a session's command.
+const PROFILE: &str =
+    "[ -f /etc/profile ] && . /etc/profile; [ -f $HOME/.profile ] && . $HOME/.profile; ";
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GreetdRequest {
+    CreateSession {
+        username: String,
+    },
+    PostAuthMessageResponse {
+        response: Option<String>,
+    },
+    StartSession {
+        cmd: Vec<String>,
+        #[serde(default)]
+        env: Vec<String>,
+    },
+    CancelSession,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ErrorType {
+    AuthError,
+    Error,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AuthMessageType {
+    Visible,
+    Secret,
+    Info,
+    Error,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GreetdResponse {
+    Success,
+    Error {
+        error_type: ErrorType,
+        description: String,
+    },
+    AuthMessage {
+        auth_message_type: AuthMessageType,
+        auth_message: String,
+    },
+}
+
+/// The greetd IPC protocol: native-endian `u32` length, then a JSON object.
+pub struct Greetd {
+    stream: UnixStream,
+    authenticating: bool,
+}
+
+impl Greetd {
+    pub fn new(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            authenticating: false,
+        }
+    }
+}
+
+impl Codec for Greetd {
+    const ACKNOWLEDGE_MESSAGES: bool = true;
+
+    fn receive(&mut self) -> Result<Option<Request>> {
+        let mut len = [0u8; 4];
+
+        match (&self.stream).read_exact(&mut len) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?,
+        }
+
+        let len = u32::from_ne_bytes(len) as usize;
+        if len > MAX_PAYLOAD_LEN {
+            return Err(Error::Protocol(format!(
+                "message of {len} bytes is too large"
+            )));
+        }
+
+        let mut payload = vec![0u8; len];
+        (&self.stream).read_exact(&mut payload)?;
+
+        let request = serde_json::from_slice::<GreetdRequest>(&payload);
+        payload.fill(0);
+
+        let request = match request.map_err(|e| Error::Protocol(e.to_string()))? {
+            GreetdRequest::CreateSession { username } => Request::CreateSession { user: username },
+            GreetdRequest::PostAuthMessageResponse { response } => {
+                Request::AnswerPrompt { answer: response }
+            }
+            GreetdRequest::StartSession { cmd, env } => Request::StartSession {
+                command: shell_command(&cmd),
+                env,
+            },
+            GreetdRequest::CancelSession => Request::Cancel,
+        };
+
+        self.authenticating = matches!(
+            request,
+            Request::CreateSession { .. } | Request::AnswerPrompt { .. }
+        );
+
+        Ok(Some(request))
+    }
+
+    fn send(&mut self, response: &Response) -> Result<()> {
+        let message = |auth_message_type, text: &String| GreetdResponse::AuthMessage {
+            auth_message_type,
+            auth_message: text.clone(),
+        };
+
+        let response = match response {
+            Response::Prompt { secret: true, text } => message(AuthMessageType::Secret, text),
+            Response::Prompt {
+                secret: false,
+                text,
+            } => message(AuthMessageType::Visible, text),
+            Response::Info(text) => message(AuthMessageType::Info, text),
+            Response::Error(text) => message(AuthMessageType::Error, text),
+            Response::Success => GreetdResponse::Success,
+            Response::Failure(reason) => GreetdResponse::Error {
+                error_type: match self.authenticating {
+                    true => ErrorType::AuthError,
+                    false => ErrorType::Error,
+                },
+                description: reason.clone(),
+            },
+        };
+
+        let payload = serde_json::to_vec(&response).map_err(|e| Error::Protocol(e.to_string()))?;
+
+        let mut frame = Vec::with_capacity(4 + payload.len());
+        frame.extend((payload.len() as u32).to_ne_bytes());
+        frame.extend(payload);
+
+        Ok((&self.stream).write_all(&frame)?)
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            stream: self.stream.try_clone()?,
+            authenticating: self.authenticating,
+        })
+    }
+}
+
+impl AsFd for Greetd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+/// greetd's `cmd` is a command line split anywhere, tuigreet sends it whole:
+/// like greetd, join it and leave it to the shell, after the profile. An
+/// empty one still asks for the built-in session.
+fn shell_command(cmd: &[String]) -> Vec<String> {
+    if cmd.is_empty() {
+        return Vec::new();
+    }
+
+    vec![
+        String::from("/bin/sh"),
+        String::from("-c"),
+        format!("{PROFILE}exec {}", cmd.join(" ")),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(stream: &mut UnixStream, json: &str) {
+        stream
+            .write_all(&(json.len() as u32).to_ne_bytes())
+            .unwrap();
+        stream.write_all(json.as_bytes()).unwrap();
+    }
+
+    fn read(stream: &mut UnixStream) -> String {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).unwrap();
+
+        let mut payload = vec![0; u32::from_ne_bytes(len) as usize];
+        stream.read_exact(&mut payload).unwrap();
+        String::from_utf8(payload).unwrap()
+    }
+
+    #[test]
+    fn translates_a_login() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut codec = Greetd::new(server);
+
+        write(
+            &mut client,
+            r#"{"type":"create_session","username":"erin"}"#,
+        );
+        assert_eq!(
+            codec.receive().unwrap(),
+            Some(Request::CreateSession {
+                user: String::from("erin")
+            })
+        );
+
+        codec
+            .send(&Response::Prompt {
+                secret: true,
+                text: String::from("Password: "),
+            })
+            .unwrap();
+        assert_eq!(
+            read(&mut client),
+            r#"{"type":"auth_message","auth_message_type":"secret","auth_message":"Password: "}"#
+        );
+
+        write(
+            &mut client,
+            r#"{"type":"post_auth_message_response","response":"hunter2"}"#,
+        );
+        assert_eq!(
+            codec.receive().unwrap(),
+            Some(Request::AnswerPrompt {
+                answer: Some(String::from("hunter2"))
+            })
+        );
+
+        codec
+            .send(&Response::Failure(String::from("bad password")))
+            .unwrap();
+        assert_eq!(
+            read(&mut client),
+            r#"{"type":"error","error_type":"auth_error","description":"bad password"}"#
+        );
+
+        write(&mut client, r#"{"type":"start_session","cmd":["sway"]}"#);
+        assert_eq!(
+            codec.receive().unwrap(),
+            Some(Request::StartSession {
+                command: shell_command(&[String::from("sway")]),
+                env: Vec::new(),
+            })
+        );
+
+        codec
+            .send(&Response::Failure(String::from("no authenticated session")))
+            .unwrap();
+        assert_eq!(
+            read(&mut client),
+            r#"{"type":"error","error_type":"error","description":"no authenticated session"}"#
+        );
+
+        write(&mut client, r#"{"type":"cancel_session"}"#);
+        assert_eq!(codec.receive().unwrap(), Some(Request::Cancel));
+
+        drop(client);
+        assert_eq!(codec.receive().unwrap(), None);
+    }
+
+    #[test]
+    fn runs_cmd_through_the_shell_like_greetd() {
+        let whole = [String::from("sway --unsupported-gpu")];
+        let split = [String::from("sway"), String::from("--unsupported-gpu")];
+
+        assert_eq!(
+            shell_command(&whole),
+            [
+                "/bin/sh",
+                "-c",
+                &format!("{PROFILE}exec sway --unsupported-gpu")
+            ]
+        );
+        assert_eq!(shell_command(&split), shell_command(&whole));
+        assert!(shell_command(&[]).is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let cmd = [format!("echo \"$HOME\" one  two > {}", out.display())];
+
+        let command = shell_command(&cmd);
+        let status = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .env("HOME", dir.path())
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            format!("{} one two\n", dir.path().display())
+        );
+    }
+
+    #[test]
+    fn rejects_bad_messages() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut codec = Greetd::new(server);
+
+        client
+            .write_all(&(MAX_PAYLOAD_LEN as u32 + 1).to_ne_bytes())
+            .unwrap();
+        assert!(matches!(codec.receive(), Err(Error::Protocol(_))));
+
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let mut codec = Greetd::new(server);
+
+        write(&mut client, r#"{"type":"unlock_session"}"#);
+        assert!(matches!(codec.receive(), Err(Error::Protocol(_))));
+    }
+}
@@ -0,0 +1,468 @@
+use std::{
+    cell::{Cell, RefCell},
+    ffi::{OsStr, OsString},
+    os::{
+        fd::{AsFd, BorrowedFd, OwnedFd},
+        unix::{fs::PermissionsExt, net::UnixListener},
+    },
+    path::Path,
+    rc::Rc,
+    time::Duration,
+};
+
+use authkit::{Conversation, ErrorCode, Pam};
+use nix::{
+    errno::Errno,
+    poll::{PollFd, PollFlags, PollTimeout},
+    sys::{
+        socket::{getsockopt, sockopt::PeerCredentials},
+        wait::WaitStatus,
+    },
+    unistd::Uid,
+};
+
+use crate::{
+    auth,
+    greetd::Greetd,
+    ipc::{Codec, Native, Request, Response},
+    log, notify,
+    signals::Signals,
+    spawn::Child,
+};
+
+use super::Result;
+
+const POLL_INTERVAL_MS: u16 = 500;
+/// How long the greeter gets to exit before it is killed.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Login {
+    pub user: String,
+    pub txn: Pam,
+    pub command: Vec<String>,
+    pub env: Vec<String>,
+}
+
+pub enum Outcome {
+    Authenticated(Login),
+    Exited(WaitStatus),
+    /// A signal asked rilm to stop or reload, the greeter was stopped for it.
+    Interrupted,
+}
+
+/// How a greeter connection ended.
+enum Handled {
+    Login(Login),
+    Closed,
+    /// A signal arrived or the greeter exited while waiting for a request.
+    Interrupted,
+}
+
+/// Waits on a greeter connection while watching for signals and for the
+/// greeter exiting. Holds its own copies of both fds, PAM keeps the
+/// conversation that uses it.
+struct Watch {
+    signals: OwnedFd,
+    greeter: OwnedFd,
+}
+
+impl Watch {
+    fn new(signals: &Signals, greeter: &Child) -> Result<Self> {
+        Ok(Self {
+            signals: signals.as_fd().try_clone_to_owned()?,
+            greeter: greeter.as_fd().try_clone_to_owned()?,
+        })
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            signals: self.signals.try_clone()?,
+            greeter: self.greeter.try_clone()?,
+        })
+    }
+
+    /// `true` once `fd` is readable, `false` if something else came first.
+    fn wait(&self, fd: BorrowedFd) -> Result<bool> {
+        loop {
+            let mut fds = [
+                PollFd::new(fd, PollFlags::POLLIN),
+                PollFd::new(self.signals.as_fd(), PollFlags::POLLIN),
+                PollFd::new(self.greeter.as_fd(), PollFlags::POLLIN),
+            ];
+
+            match nix::poll::poll(&mut fds, notify::timeout()) {
+                Err(Errno::EINTR) => continue,
+                ready => ready?,
+            };
+            notify::ping();
+
+            let [client, interrupts @ ..] = fds.map(|fd| fd.any().unwrap_or_default());
+            if interrupts.contains(&true) {
+                return Ok(false);
+            }
+            if client {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Bind the socket the greeter talks to, only accessible by `owner` (or the
+/// current user).
+pub fn bind(path: &Path, owner: Option<Uid>) -> Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    if let Some(owner) = owner {
+        nix::unistd::chown(path, Some(owner), None)?;
+    }
+
+    Ok(listener)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Protocol {
+    Native,
+    Greetd,
+}
+
+/// Answer requests from the greeter until a session is started or the
+/// greeter exits. Only connections from `allowed` are served. However
+/// serving ends, the greeter is stopped with everything it started.
+pub fn serve(
+    listeners: &[(&UnixListener, Protocol)],
+    greeter: &Child,
+    signals: &Signals,
+    allowed: Uid,
+    service: &str,
+) -> Result<Outcome> {
+    let outcome = accept(listeners, greeter, signals, allowed, service);
+
+    if let Err(e) = stop(greeter) {
+        log::error!("Failed to stop the greeter: {e}");
+    }
+
+    outcome
+}
+
+fn accept(
+    listeners: &[(&UnixListener, Protocol)],
+    greeter: &Child,
+    signals: &Signals,
+    allowed: Uid,
+    service: &str,
+) -> Result<Outcome> {
+    loop {
+        notify::ping();
+
+        if let Some(status) = greeter.try_wait()? {
+            return Ok(Outcome::Exited(status));
+        }
+
+        let mut fds = listeners
+            .iter()
+            .map(|(listener, _)| PollFd::new(listener.as_fd(), PollFlags::POLLIN))
+            .chain([
+                PollFd::new(greeter.as_fd(), PollFlags::POLLIN),
+                PollFd::new(signals.as_fd(), PollFlags::POLLIN),
+            ])
+            .collect::<Vec<_>>();
+
+        match nix::poll::poll(&mut fds, PollTimeout::from(POLL_INTERVAL_MS)) {
+            Ok(0) | Err(Errno::EINTR) => continue,
+            ready => ready?,
+        };
+
+        signals.read()?;
+        if signals.pending() {
+            return Ok(Outcome::Interrupted);
+        }
+
+        let ready = fds
+            .iter()
+            .map(|fd| fd.any().unwrap_or_default())
+            .collect::<Vec<_>>();
+
+        for (&(listener, protocol), _) in listeners.iter().zip(ready).filter(|(_, ready)| *ready) {
+            let (stream, _) = listener.accept()?;
+
+            let peer = getsockopt(&stream, PeerCredentials)?;
+            if peer.uid() != allowed.as_raw() {
+                log::warning!(
+                    "Refused greeter connection from uid {} (pid {})",
+                    peer.uid(),
+                    peer.pid()
+                );
+                continue;
+            }
+
+            let watch = Watch::new(signals, greeter)?;
+            let handled = match protocol {
+                Protocol::Native => handle_client(Native(stream), watch, service),
+                Protocol::Greetd => handle_client(Greetd::new(stream), watch, service),
+            };
+
+            match handled {
+                Ok(Handled::Login(login)) => return Ok(Outcome::Authenticated(login)),
+                Ok(Handled::Closed) => {}
+                // Handled at the top of the loop.
+                Ok(Handled::Interrupted) => break,
+                Err(e) => log::warning!("Greeter connection failed: {e}"),
+            }
+        }
+    }
+}
+
+/// SIGTERM the greeter and what it started, SIGKILL what is still running
+/// after [`STOP_TIMEOUT`].
+fn stop(greeter: &Child) -> Result<WaitStatus> {
+    let status = greeter.stop(STOP_TIMEOUT)?;
+
+    let killed = greeter.stop_scope(STOP_TIMEOUT)?;
+    if !killed.is_empty() {
+        let killed = killed.iter().map(ToString::to_string).collect::<Vec<_>>();
+        log::warning!(
+            "Killed what the greeter left running: {}",
+            killed.join(", ")
+        );
+    }
+
+    Ok(status)
+}
+
+fn handle_client<C: Codec + 'static>(mut codec: C, watch: Watch, service: &str) -> Result<Handled> {
+    let mut authenticated: Option<(String, Pam)> = None;
+
+    loop {
+        if !watch.wait(codec.as_fd())? {
+            return Ok(Handled::Interrupted);
+        }
+
+        let Some(request) = codec.receive()? else {
+            return Ok(Handled::Closed);
+        };
+
+        match request {
+            Request::CreateSession { user } => {
+                authenticated = None;
+
+                let conversation = IpcConversation {
+                    codec: RefCell::new(codec.try_clone()?),
+                    watch: watch.try_clone()?,
+                    cancelled: Rc::new(Cell::new(false)),
+                };
+                let cancelled = conversation.cancelled.clone();
+
+                match auth::authenticate(service, &user, conversation) {
+                    Ok(txn) => {
+                        codec.send(&Response::Success)?;
+                        authenticated = Some((user, txn));
+                    }
+                    // greetd answers a cancel during authentication like any other.
+                    Err(_) if cancelled.get() => codec.send(&Response::Success)?,
+                    Err(e) => codec.send(&Response::Failure(e.to_string()))?,
+                }
+            }
+            Request::StartSession { command, env } => match authenticated.take() {
+                Some((user, txn)) => {
+                    codec.send(&Response::Success)?;
+                    return Ok(Handled::Login(Login {
+                        user,
+                        txn,
+                        command,
+                        env,
+                    }));
+                }
+                None => codec.send(&Response::Failure(String::from("no authenticated session")))?,
+            },
+            Request::Cancel => {
+                authenticated = None;
+                codec.send(&Response::Success)?;
+            }
+            Request::AnswerPrompt { .. } => {
+                codec.send(&Response::Failure(String::from("no pending prompt")))?
+            }
+        }
+    }
+}
+
+/// Forwards PAM's questions and messages to the greeter, and blocks for its
+/// answers.
+struct IpcConversation<C: Codec> {
+    codec: RefCell<C>,
+    watch: Watch,
+    /// Set when the greeter cancelled instead of answering, which aborts the
+    /// conversation.
+    cancelled: Rc<Cell<bool>>,
+}
+
+impl<C: Codec> IpcConversation<C> {
+    fn exchange(&self, response: Response) -> authkit::Result<Option<String>> {
+        let mut codec = self.codec.borrow_mut();
+
+        codec
+            .send(&response)
+            .map_err(|_| ErrorCode::ConversationError)?;
+
+        if !self
+            .watch
+            .wait(codec.as_fd())
+            .map_err(|_| ErrorCode::ConversationError)?
+        {
+            return Err(ErrorCode::ConversationError);
+        }
+
+        match codec.receive() {
+            Ok(Some(Request::AnswerPrompt { answer })) => Ok(answer),
+            Ok(Some(Request::Cancel)) => {
+                self.cancelled.set(true);
+                Err(ErrorCode::ConversationError)
+            }
+            _ => Err(ErrorCode::ConversationError),
+        }
+    }
+
+    fn ask(&self, question: &OsStr, secret: bool) -> authkit::Result<OsString> {
+        let answer = self.exchange(Response::Prompt {
+            secret,
+            text: question.to_string_lossy().into_owned(),
+        })?;
+
+        Ok(OsString::from(answer.unwrap_or_default()))
+    }
+
+    fn tell(&self, response: Response) {
+        match C::ACKNOWLEDGE_MESSAGES {
+            true => drop(self.exchange(response)),
+            false => drop(self.codec.borrow_mut().send(&response)),
+        }
+    }
+}
+
+impl<C: Codec> Conversation for IpcConversation<C> {
+    fn prompt(&self, question: &OsStr) -> authkit::Result<OsString> {
+        self.ask(question, false)
+    }
+
+    fn masked_prompt(&self, question: &OsStr) -> authkit::Result<OsString> {
+        self.ask(question, true)
+    }
+
+    fn info_msg(&self, message: &OsStr) {
+        self.tell(Response::Info(message.to_string_lossy().into_owned()))
+    }
+
+    fn error_msg(&self, message: &OsStr) {
+        self.tell(Response::Error(message.to_string_lossy().into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixStream,
+    };
+
+    use nix::sys::signal::Signal;
+
+    use super::*;
+    use crate::spawn::Spec;
+
+    #[test]
+    fn greeter_is_stopped_when_serving_ends() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = bind(&dir.path().join("greeter.sock"), None).unwrap();
+        let signals = Signals::install().unwrap();
+        let greeter = Spec::new("sleep").arg("60").isolate().spawn().unwrap();
+
+        nix::sys::signal::raise(Signal::SIGTERM).unwrap();
+        let outcome = serve(
+            &[(&listener, Protocol::Native)],
+            &greeter,
+            &signals,
+            Uid::current(),
+            "rilm",
+        )
+        .unwrap();
+
+        assert!(matches!(outcome, Outcome::Interrupted));
+        assert_eq!(
+            greeter.try_wait().unwrap(),
+            Some(WaitStatus::Signaled(greeter.pid(), Signal::SIGTERM, false))
+        );
+    }
+
+    #[test]
+    fn watch_gives_up_on_signals_and_greeter_exit() {
+        let (signals, mut signal) = UnixStream::pair().unwrap();
+        let (greeter, exit) = UnixStream::pair().unwrap();
+        let (client, mut greeter_client) = UnixStream::pair().unwrap();
+
+        let watch = Watch {
+            signals: signals.into(),
+            greeter: greeter.into(),
+        };
+
+        greeter_client.write_all(b"request").unwrap();
+        assert!(watch.wait(client.as_fd()).unwrap());
+
+        signal.write_all(b"SIGTERM").unwrap();
+        assert!(!watch.wait(client.as_fd()).unwrap());
+
+        let (quiet, _sender) = UnixStream::pair().unwrap();
+        let watch = Watch {
+            signals: quiet.into(),
+            greeter: watch.greeter,
+        };
+        assert!(watch.wait(client.as_fd()).unwrap());
+
+        drop(exit);
+        assert!(!watch.wait(client.as_fd()).unwrap());
+    }
+
+    #[test]
+    fn cancel_aborts_the_conversation() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let (quiet, _sender) = UnixStream::pair().unwrap();
+
+        let conversation = IpcConversation {
+            codec: RefCell::new(Greetd::new(server)),
+            watch: Watch {
+                signals: quiet.try_clone().unwrap().into(),
+                greeter: quiet.into(),
+            },
+            cancelled: Rc::new(Cell::new(false)),
+        };
+
+        let cancel = br#"{"type":"cancel_session"}"#;
+        client
+            .write_all(&(cancel.len() as u32).to_ne_bytes())
+            .unwrap();
+        client.write_all(cancel).unwrap();
+
+        assert!(matches!(
+            conversation.masked_prompt(OsStr::new("Password: ")),
+            Err(ErrorCode::ConversationError)
+        ));
+        assert!(conversation.cancelled.get());
+
+        let mut len = [0; 4];
+        client.read_exact(&mut len).unwrap();
+        let mut prompt = vec![0; u32::from_ne_bytes(len) as usize];
+        client.read_exact(&mut prompt).unwrap();
+        assert_eq!(
+            String::from_utf8(prompt).unwrap(),
+            r#"{"type":"auth_message","auth_message_type":"secret","auth_message":"Password: "}"#
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use std::{
+    io::ErrorKind,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{Config, Systemd},
+    systemd,
+    sysuser::Account,
+};
+
+use super::{Error, Result};
+
+const MARKER: &str = "# Managed by `rilm patch-config`, local changes will be overwritten.";
+const BACKUP_SUFFIX: &str = ".rilm-backup";
+const DIFF_CONTEXT: usize = 3;
+
+const GREETER_PAM: &str = r#"#%PAM-1.0
+
+# The greeter account has no password
+auth       required     pam_env.so
+auth       required     pam_permit.so
+
+account    required     pam_permit.so
+
+password   required     pam_deny.so
+
+session    required     pam_unix.so
+session    optional     pam_systemd.so
+"#;
+
+/// The distribution's own login stack, whatever it is made of.
+const SESSION_PAM: &str = r#"#%PAM-1.0
+
+auth       include      login
+account    include      login
+password   include      login
+session    include      login
+"#;
+
+const AUTOLOGIN_PAM: &str = r#"#%PAM-1.0
+
+# No password, the user is the one set in rilm's configuration
+auth       required     pam_env.so
+auth       required     pam_permit.so
+
+account    include      login
+
+password   required     pam_deny.so
+
+session    include      login
+"#;
+
+const UNIT: &str = r#"[Unit]
+Description=RILM Login Manager
+After=systemd-user-sessions.service plymouth-quit-wait.service
+After=getty@tty{tty}.service
+Conflicts=getty@tty{tty}.service
+
+[Service]
+Type=notify
+NotifyAccess=main
+WatchdogSec=30s
+ExecStart={exe}
+IgnoreSIGPIPE=no
+SendSIGHUP=yes
+KeyringMode=shared
+Delegate=yes
+Restart=always
+
+[Install]
+Alias=display-manager.service
+"#;
+
+pub struct Options {
+    /// Prefix every installed path with this directory, for staging.
+    pub root: PathBuf,
+    pub dry_run: bool,
+    pub uninstall: bool,
+}
+
+/// A file `patch-config` owns on the system.
+pub struct Artifact {
+    pub path: String,
+    pub mode: u32,
+    pub contents: String,
+    /// Removed on install too, for files that are no longer wanted.
+    pub remove: bool,
+}
+
+pub fn artifacts(exe: &Path, config: &Config) -> Vec<Artifact> {
+    let managed = |contents: &str| match contents.split_once('\n') {
+        Some((shebang, rest)) if shebang.starts_with("#%") => {
+            format!("{shebang}\n{MARKER}\n{rest}")
+        }
+        _ => format!("{MARKER}\n\n{contents}"),
+    };
+
+    let mut artifacts = vec![
+        Artifact {
+            path: format!("/etc/pam.d/{}", config.greeter.pam_service),
+            mode: 0o644,
+            contents: managed(GREETER_PAM),
+            remove: false,
+        },
+        Artifact {
+            path: format!("/etc/pam.d/{}", config.session.pam_service),
+            mode: 0o644,
+            contents: managed(SESSION_PAM),
+            remove: false,
+        },
+        Artifact {
+            path: String::from("/etc/sysusers.d/rilm.conf"),
+            mode: 0o644,
+            contents: managed(&Account::greeter(&config.greeter).sysusers()),
+            remove: false,
+        },
+        Artifact {
+            path: String::from("/etc/systemd/system/rilm.service"),
+            mode: 0o644,
+            contents: managed(
+                &UNIT
+                    .replace("{exe}", &exe.display().to_string())
+                    .replace("{tty}", &config.display.tty.to_string()),
+            ),
+            remove: false,
+        },
+    ];
+
+    // A service that lets anyone in only exists while autologin is on, it is
+    // still listed afterwards so that it gets removed.
+    artifacts.push(Artifact {
+        path: format!("/etc/pam.d/{}", config.autologin.pam_service),
+        mode: 0o644,
+        contents: managed(AUTOLOGIN_PAM),
+        remove: config.autologin.user.is_none(),
+    });
+
+    // Likewise for the session target once systemd integration is off.
+    artifacts.push(Artifact {
+        path: String::from(systemd::SESSION_TARGET_PATH),
+        mode: 0o644,
+        contents: managed(systemd::SESSION_TARGET_UNIT),
+        remove: config.session.systemd == Systemd::Off,
+    });
+
+    artifacts
+}
+
+pub fn run(artifacts: &[Artifact], options: &Options) -> Result<()> {
+    for artifact in artifacts {
+        let target = options.root.join(artifact.path.trim_start_matches('/'));
+
+        match options.uninstall || artifact.remove {
+            true => uninstall(&target, options.dry_run)?,
+            false => install(artifact, &target, options.dry_run)?,
+        }
+    }
+
+    Ok(())
+}
+
+fn install(artifact: &Artifact, target: &Path, dry_run: bool) -> Result<()> {
+    let current = read(target)?;
+    let current_mode = mode(target)?;
+
+    if current.as_deref() == Some(artifact.contents.as_str()) && current_mode == Some(artifact.mode)
+    {
+        println!("{}: up to date", target.display());
+        return Ok(());
+    }
+
+    if dry_run {
+        match current.as_deref() == Some(artifact.contents.as_str()) {
+            true => println!(
+                "{}: would change mode {:o} -> {:o}",
+                target.display(),
+                current_mode.unwrap_or_default(),
+                artifact.mode
+            ),
+            false => print!(
+                "{}",
+                unified_diff(target, current.as_deref().unwrap_or(""), &artifact.contents)
+            ),
+        }
+
+        return Ok(());
+    }
+
+    let dir = target.parent().ok_or(Error::Install(format!(
+        "{} has no parent",
+        target.display()
+    )))?;
+    std::fs::create_dir_all(dir)?;
+    check_dir(dir)?;
+
+    let backup = backup_path(target);
+    if let Some(current) = &current
+        && !current.contains(MARKER)
+        && !backup.exists()
+    {
+        std::fs::copy(target, &backup)?;
+        println!(
+            "{}: saved previous file to {}",
+            target.display(),
+            backup.display()
+        );
+    }
+
+    let staging = target.with_extension("rilm-new");
+    std::fs::write(&staging, &artifact.contents)?;
+    std::fs::set_permissions(&staging, std::fs::Permissions::from_mode(artifact.mode))?;
+    std::fs::rename(&staging, target)?;
+
+    match mode(target)? {
+        Some(mode) if mode == artifact.mode => {}
+        mode => {
+            return Err(Error::Install(format!(
+                "{} has mode {:o} instead of {:o}",
+                target.display(),
+                mode.unwrap_or_default(),
+                artifact.mode
+            )));
+        }
+    }
+
+    println!("{}: installed", target.display());
+    Ok(())
+}
+
+fn uninstall(target: &Path, dry_run: bool) -> Result<()> {
+    let current = read(target)?;
+    let backup = backup_path(target);
+    let previous = read(&backup)?;
+
+    let ours = current.as_deref().is_some_and(|c| c.contains(MARKER));
+    if !ours && previous.is_none() {
+        println!("{}: not installed by rilm, left alone", target.display());
+        return Ok(());
+    }
+
+    if dry_run {
+        print!(
+            "{}",
+            unified_diff(
+                target,
+                current.as_deref().unwrap_or(""),
+                previous.as_deref().unwrap_or("")
+            )
+        );
+        return Ok(());
+    }
+
+    match previous {
+        Some(_) => {
+            std::fs::rename(&backup, target)?;
+            println!("{}: restored from {}", target.display(), backup.display());
+        }
+        None => {
+            std::fs::remove_file(target)?;
+            println!("{}: removed", target.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn backup_path(target: &Path) -> PathBuf {
+    let mut backup = target.as_os_str().to_owned();
+    backup.push(BACKUP_SUFFIX);
+    PathBuf::from(backup)
+}
+
+fn read(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn mode(path: &Path) -> Result<Option<u32>> {
+    match std::fs::metadata(path) {
+        Ok(metadata) => Ok(Some(metadata.mode() & 0o7777)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// PAM and systemd read these directories as root, nobody else may be able
+/// to write there.
+fn check_dir(dir: &Path) -> Result<()> {
+    let mode = std::fs::metadata(dir)?.mode();
+
+    match mode & 0o022 {
+        0 => Ok(()),
+        _ => Err(Error::Install(format!(
+            "{} is writable by other users (mode {:o})",
+            dir.display(),
+            mode & 0o7777
+        ))),
+    }
+}
+
+/// Line based diff of `old` against `new`, in the unified format.
+pub fn unified_diff(path: &Path, old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    // (old line, new line, tag)
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            edits.push((i, j, ' '));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            edits.push((i, j, '-'));
+            i += 1;
+        } else {
+            edits.push((i, j, '+'));
+            j += 1;
+        }
+    }
+
+    let changed = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, _, tag))| *tag != ' ')
+        .map(|(k, _)| k)
+        .collect::<Vec<_>>();
+
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    let mut diff = format!("--- {0}\n+++ {0}\n", path.display());
+
+    let mut k = 0;
+    while k < changed.len() {
+        let start = changed[k].saturating_sub(DIFF_CONTEXT);
+        let mut end = changed[k];
+        while k < changed.len() && changed[k] <= end + 2 * DIFF_CONTEXT {
+            end = changed[k];
+            k += 1;
+        }
+        let end = (end + DIFF_CONTEXT + 1).min(edits.len());
+
+        let hunk = &edits[start..end];
+        let old_len = hunk.iter().filter(|(_, _, tag)| *tag != '+').count();
+        let new_len = hunk.iter().filter(|(_, _, tag)| *tag != '-').count();
+        let (old_start, new_start) = (hunk[0].0, hunk[0].1);
+
+        diff.push_str(&format!(
+            "@@ -{},{old_len} +{},{new_len} @@\n",
+            old_start + (old_len > 0) as usize,
+            new_start + (new_len > 0) as usize
+        ));
+
+        for &(i, j, tag) in hunk {
+            let line = match tag {
+                '+' => new[j],
+                _ => old[i],
+            };
+            diff.push_str(&format!("{tag}{line}\n"));
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(root: &Path, dry_run: bool, uninstall: bool) -> Options {
+        Options {
+            root: root.to_path_buf(),
+            dry_run,
+            uninstall,
+        }
+    }
+
+    #[test]
+    fn install_is_idempotent_and_uninstall_restores() {
+        let root = tempfile::tempdir().unwrap();
+        let pam = root.path().join("etc/pam.d");
+        std::fs::create_dir_all(&pam).unwrap();
+        std::fs::set_permissions(&pam, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(pam.join("rilm"), "previous\n").unwrap();
+
+        let artifacts = artifacts(Path::new("/usr/bin/rilm"), &Config::default());
+
+        run(&artifacts, &options(root.path(), false, false)).unwrap();
+        run(&artifacts, &options(root.path(), false, false)).unwrap();
+
+        let installed = std::fs::read_to_string(pam.join("rilm")).unwrap();
+        assert!(installed.starts_with("#%PAM-1.0\n# Managed by"));
+        assert_eq!(
+            std::fs::read_to_string(pam.join("rilm.rilm-backup")).unwrap(),
+            "previous\n"
+        );
+        assert_eq!(mode(&pam.join("rilm-greeter")).unwrap(), Some(0o644));
+
+        let unit = root.path().join("etc/systemd/system/rilm.service");
+        let contents = std::fs::read_to_string(&unit).unwrap();
+        assert!(contents.contains("ExecStart=/usr/bin/rilm\n"));
+        assert!(contents.contains("Conflicts=getty@tty1.service\n"));
+
+        run(&artifacts, &options(root.path(), false, true)).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(pam.join("rilm")).unwrap(),
+            "previous\n"
+        );
+        assert!(!pam.join("rilm.rilm-backup").exists());
+        assert!(!pam.join("rilm-greeter").exists());
+        assert!(!unit.exists());
+    }
+
+    #[test]
+    fn autologin_service_is_removed_once_disabled() {
+        let root = tempfile::tempdir().unwrap();
+        let pam = root.path().join("etc/pam.d/rilm-autologin");
+
+        let mut config = Config::default();
+        config.autologin.user = Some(String::from("kiosk"));
+
+        run(
+            &artifacts(Path::new("/usr/bin/rilm"), &config),
+            &options(root.path(), false, false),
+        )
+        .unwrap();
+        assert!(
+            std::fs::read_to_string(&pam)
+                .unwrap()
+                .contains("pam_permit.so")
+        );
+
+        config.autologin.user = None;
+        let artifacts = artifacts(Path::new("/usr/bin/rilm"), &config);
+
+        run(&artifacts, &options(root.path(), false, false)).unwrap();
+        assert!(!pam.exists());
+
+        // A file rilm did not write is left alone.
+        std::fs::write(&pam, "local\n").unwrap();
+        run(&artifacts, &options(root.path(), false, false)).unwrap();
+        assert_eq!(std::fs::read_to_string(&pam).unwrap(), "local\n");
+    }
+
+    #[test]
+    fn session_target_is_removed_once_systemd_is_off() {
+        let root = tempfile::tempdir().unwrap();
+        let target = root.path().join("usr/lib/systemd/user/rilm-session.target");
+
+        let mut config = Config::default();
+        config.session.systemd = Systemd::Target;
+
+        run(
+            &artifacts(Path::new("/usr/bin/rilm"), &config),
+            &options(root.path(), false, false),
+        )
+        .unwrap();
+        assert!(target.exists());
+
+        config.session.systemd = Systemd::Off;
+        run(
+            &artifacts(Path::new("/usr/bin/rilm"), &config),
+            &options(root.path(), false, false),
+        )
+        .unwrap();
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn unit_replaces_the_getty_of_the_configured_vt() {
+        let mut config = Config::default();
+        config.display.tty = 7;
+
+        let artifacts = artifacts(Path::new("/usr/bin/rilm"), &config);
+        let unit = artifacts
+            .iter()
+            .find(|artifact| artifact.path.ends_with("rilm.service"))
+            .unwrap();
+
+        assert!(unit.contents.contains("After=getty@tty7.service\n"));
+        assert!(unit.contents.contains("Conflicts=getty@tty7.service\n"));
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        let root = tempfile::tempdir().unwrap();
+
+        run(
+            &artifacts(Path::new("/usr/bin/rilm"), &Config::default()),
+            &options(root.path(), true, false),
+        )
+        .unwrap();
+
+        assert!(!root.path().join("etc").exists());
+    }
+
+    #[test]
+    fn diff_has_context_and_hunks() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\n";
+
+        assert_eq!(
+            unified_diff(Path::new("/x"), old, new),
+            "--- /x\n+++ /x\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n"
+        );
+        assert_eq!(unified_diff(Path::new("/x"), old, old), "");
+    }
+}
@@ -0,0 +1,438 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    os::{
+        fd::{AsFd, BorrowedFd},
+        unix::net::UnixStream,
+    },
+    path::Path,
+};
+
+use super::{Error, Result};
+
+pub const SOCKET_ENV: &str = "RILM_SOCKET";
+pub const VERSION: u8 = 1;
+
+const HEADER_LEN: usize = 6;
+const MAX_PAYLOAD_LEN: usize = 64 * 1024;
+
+/// Sent by the greeter to the root process.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    CreateSession {
+        user: String,
+    },
+    AnswerPrompt {
+        answer: Option<String>,
+    },
+    Cancel,
+    StartSession {
+        command: Vec<String>,
+        env: Vec<String>,
+    },
+}
+
+/// Sent by the root process to the greeter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    Prompt { secret: bool, text: String },
+    Info(String),
+    Error(String),
+    Success,
+    Failure(String),
+}
+
+pub trait Message: Sized {
+    fn encode(&self, payload: &mut Encoder) -> u8;
+
+    fn decode(tag: u8, payload: &mut Decoder) -> Result<Self>;
+}
+
+impl Message for Request {
+    fn encode(&self, payload: &mut Encoder) -> u8 {
+        match self {
+            Self::CreateSession { user } => {
+                payload.string(user);
+                1
+            }
+            Self::AnswerPrompt { answer } => {
+                payload.optional(answer.as_deref());
+                2
+            }
+            Self::Cancel => 3,
+            Self::StartSession { command, env } => {
+                payload.list(command);
+                payload.list(env);
+                4
+            }
+        }
+    }
+
+    fn decode(tag: u8, payload: &mut Decoder) -> Result<Self> {
+        Ok(match tag {
+            1 => Self::CreateSession {
+                user: payload.string()?,
+            },
+            2 => Self::AnswerPrompt {
+                answer: payload.optional()?,
+            },
+            3 => Self::Cancel,
+            4 => Self::StartSession {
+                command: payload.list()?,
+                env: payload.list()?,
+            },
+            tag => return Err(Error::Protocol(format!("unknown request {tag}"))),
+        })
+    }
+}
+
+impl Message for Response {
+    fn encode(&self, payload: &mut Encoder) -> u8 {
+        match self {
+            Self::Prompt { secret, text } => {
+                payload.byte(*secret as u8);
+                payload.string(text);
+                1
+            }
+            Self::Info(text) => {
+                payload.string(text);
+                2
+            }
+            Self::Error(text) => {
+                payload.string(text);
+                3
+            }
+            Self::Success => 4,
+            Self::Failure(reason) => {
+                payload.string(reason);
+                5
+            }
+        }
+    }
+
+    fn decode(tag: u8, payload: &mut Decoder) -> Result<Self> {
+        Ok(match tag {
+            1 => Self::Prompt {
+                secret: payload.byte()? != 0,
+                text: payload.string()?,
+            },
+            2 => Self::Info(payload.string()?),
+            3 => Self::Error(payload.string()?),
+            4 => Self::Success,
+            5 => Self::Failure(payload.string()?),
+            tag => return Err(Error::Protocol(format!("unknown response {tag}"))),
+        })
+    }
+}
+
+/// Write one frame: version, tag, big-endian payload length, payload.
+pub fn send(mut writer: impl Write, message: &impl Message) -> Result<()> {
+    let mut payload = Encoder(Vec::new());
+    let tag = message.encode(&mut payload);
+
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.0.len());
+    frame.push(VERSION);
+    frame.push(tag);
+    frame.extend((payload.0.len() as u32).to_be_bytes());
+    frame.extend(&payload.0);
+
+    let result = writer.write_all(&frame);
+
+    payload.0.fill(0);
+    frame.fill(0);
+
+    Ok(result?)
+}
+
+/// Read one frame, `None` if the peer closed the connection between frames.
+pub fn receive<M: Message>(mut reader: impl Read) -> Result<Option<M>> {
+    let mut header = [0u8; HEADER_LEN];
+
+    match reader.read_exact(&mut header) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+
+    let [version, tag, len @ ..] = header;
+    if version != VERSION {
+        return Err(Error::Protocol(format!(
+            "unsupported protocol version {version}, expected {VERSION}"
+        )));
+    }
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_PAYLOAD_LEN {
+        return Err(Error::Protocol(format!(
+            "frame of {len} bytes is too large"
+        )));
+    }
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+
+    let mut decoder = Decoder(&payload);
+    let message = M::decode(tag, &mut decoder);
+    let trailing = !decoder.0.is_empty();
+
+    payload.fill(0);
+
+    match trailing {
+        true => Err(Error::Protocol(String::from("trailing bytes in frame"))),
+        false => message.map(Some),
+    }
+}
+
+pub struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn byte(&mut self, byte: u8) {
+        self.0.push(byte);
+    }
+
+    fn string(&mut self, string: &str) {
+        self.0.extend((string.len() as u32).to_be_bytes());
+        self.0.extend(string.as_bytes());
+    }
+
+    fn optional(&mut self, string: Option<&str>) {
+        match string {
+            Some(string) => {
+                self.byte(1);
+                self.string(string);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn list(&mut self, strings: &[String]) {
+        self.0.extend((strings.len() as u32).to_be_bytes());
+        for string in strings {
+            self.string(string);
+        }
+    }
+}
+
+pub struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        if self.0.len() < len {
+            return Err(Error::Protocol(String::from("truncated frame")));
+        }
+
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| Error::Protocol(String::from("string is not UTF-8")))
+    }
+
+    fn optional(&mut self) -> Result<Option<String>> {
+        match self.byte()? {
+            0 => Ok(None),
+            _ => self.string().map(Some),
+        }
+    }
+
+    fn list(&mut self) -> Result<Vec<String>> {
+        (0..self.len()?).map(|_| self.string()).collect()
+    }
+}
+
+/// Server side transport for greeter requests, so that the same login flow
+/// can be offered over rilm's protocol and over greetd's.
+pub trait Codec: AsFd + Sized {
+    /// Whether info and error messages wait for an empty answer before PAM
+    /// goes on, as greetd does.
+    const ACKNOWLEDGE_MESSAGES: bool = false;
+
+    fn receive(&mut self) -> Result<Option<Request>>;
+
+    fn send(&mut self, response: &Response) -> Result<()>;
+
+    fn try_clone(&self) -> Result<Self>;
+}
+
+pub struct Native(pub UnixStream);
+
+impl Codec for Native {
+    fn receive(&mut self) -> Result<Option<Request>> {
+        receive(&self.0)
+    }
+
+    fn send(&mut self, response: &Response) -> Result<()> {
+        send(&self.0, response)
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Self(self.0.try_clone()?))
+    }
+}
+
+impl AsFd for Native {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+/// Overwrite `secret` before freeing it.
+pub fn wipe(secret: String) {
+    let mut bytes = secret.into_bytes();
+    for byte in bytes.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+}
+
+/// Greeter side of the protocol.
+pub struct Client {
+    stream: UnixStream,
+}
+
+impl Client {
+    pub fn new(stream: UnixStream) -> Self {
+        Self { stream }
+    }
+
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(UnixStream::connect(path)?))
+    }
+
+    /// Connect to the socket the root process advertised in `RILM_SOCKET`.
+    pub fn from_env() -> Result<Self> {
+        Self::connect(std::env::var_os(SOCKET_ENV).ok_or(Error::MissingEnv(SOCKET_ENV))?)
+    }
+
+    pub fn create_session(&mut self, user: &str) -> Result<Response> {
+        self.request(&Request::CreateSession {
+            user: user.to_string(),
+        })
+    }
+
+    /// The answer may be a secret, it is wiped once sent.
+    pub fn answer(&mut self, answer: Option<String>) -> Result<Response> {
+        let request = Request::AnswerPrompt { answer };
+        let response = self.request(&request);
+
+        if let Request::AnswerPrompt {
+            answer: Some(answer),
+        } = request
+        {
+            wipe(answer);
+        }
+
+        response
+    }
+
+    pub fn start_session(&mut self, command: Vec<String>, env: Vec<String>) -> Result<Response> {
+        self.request(&Request::StartSession { command, env })
+    }
+
+    /// Wait for the next message, for when the root sends several in a row.
+    pub fn receive(&mut self) -> Result<Response> {
+        receive(&self.stream)?.ok_or(Error::IoError(ErrorKind::UnexpectedEof.into()))
+    }
+
+    fn request(&mut self, request: &Request) -> Result<Response> {
+        send(&self.stream, request)?;
+        self.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<M: Message>(message: &M) -> M {
+        let mut frame = Vec::new();
+        send(&mut frame, message).unwrap();
+
+        receive(frame.as_slice()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        let requests = [
+            Request::CreateSession {
+                user: String::from("erin"),
+            },
+            Request::AnswerPrompt {
+                answer: Some(String::from("hunter2")),
+            },
+            Request::AnswerPrompt { answer: None },
+            Request::Cancel,
+            Request::StartSession {
+                command: vec![String::from("sway"), String::from("--unsupported-gpu")],
+                env: vec![String::from("XDG_SESSION_DESKTOP=sway")],
+            },
+        ];
+        for request in requests {
+            assert_eq!(round_trip(&request), request);
+        }
+
+        let responses = [
+            Response::Prompt {
+                secret: true,
+                text: String::from("Password: "),
+            },
+            Response::Info(String::from("Welcome")),
+            Response::Error(String::from("Caps Lock")),
+            Response::Success,
+            Response::Failure(String::from("bad password")),
+        ];
+        for response in responses {
+            assert_eq!(round_trip(&response), response);
+        }
+    }
+
+    #[test]
+    fn closed_connection_between_frames_is_not_an_error() {
+        assert!(receive::<Request>(&[][..]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let mut frame = Vec::new();
+        send(&mut frame, &Request::Cancel).unwrap();
+
+        let mut other_version = frame.clone();
+        other_version[0] = VERSION + 1;
+        assert!(matches!(
+            receive::<Request>(other_version.as_slice()),
+            Err(Error::Protocol(_))
+        ));
+
+        let mut oversized = frame.clone();
+        oversized[2..6].copy_from_slice(&(MAX_PAYLOAD_LEN as u32 + 1).to_be_bytes());
+        assert!(matches!(
+            receive::<Request>(oversized.as_slice()),
+            Err(Error::Protocol(_))
+        ));
+
+        let mut trailing = frame.clone();
+        trailing[2..6].copy_from_slice(&1u32.to_be_bytes());
+        trailing.push(0);
+        assert!(matches!(
+            receive::<Request>(trailing.as_slice()),
+            Err(Error::Protocol(_))
+        ));
+
+        let mut unknown = frame;
+        unknown[1] = 0xff;
+        assert!(matches!(
+            receive::<Request>(unknown.as_slice()),
+            Err(Error::Protocol(_))
+        ));
+    }
+}
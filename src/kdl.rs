@@ -0,0 +1,217 @@
+use std::fmt::Write;
+
+const INDENT: &str = "    ";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&String> for Value {
+    fn from(value: &String) -> Self {
+        Self::String(value.clone())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Self::Int(value.into())
+    }
+}
+
+impl From<u16> for Value {
+    fn from(value: u16) -> Self {
+        Self::Int(value.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+/// A KDL node with its arguments, properties and children, in the order
+/// they were added.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    name: String,
+    args: Vec<Value>,
+    props: Vec<(String, Value)>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: Vec::new(),
+            props: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, value: impl Into<Value>) -> Self {
+        self.args.push(value.into());
+        self
+    }
+
+    pub fn args<V: Into<Value>>(mut self, values: impl IntoIterator<Item = V>) -> Self {
+        self.args.extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn prop(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.props.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    pub fn children(mut self, children: impl IntoIterator<Item = Node>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = INDENT.repeat(depth);
+
+        out.push_str(&indent);
+        write_identifier(out, &self.name);
+
+        for arg in &self.args {
+            out.push(' ');
+            write_value(out, arg);
+        }
+
+        for (key, value) in &self.props {
+            out.push(' ');
+            write_identifier(out, key);
+            out.push('=');
+            write_value(out, value);
+        }
+
+        if self.children.is_empty() {
+            out.push('\n');
+            return;
+        }
+
+        out.push_str(" {\n");
+        for child in &self.children {
+            child.write(out, depth + 1);
+        }
+        out.push_str(&indent);
+        out.push_str("}\n");
+    }
+}
+
+/// Top level nodes, separated by blank lines.
+pub fn document(nodes: &[Node]) -> String {
+    let mut out = String::new();
+
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        node.write(&mut out, 0);
+    }
+
+    out
+}
+
+fn write_identifier(out: &mut String, name: &str) {
+    let bare = !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && !matches!(name, "true" | "false" | "null")
+        && !name
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || "\\/(){}<>;[]=,\"".contains(c));
+
+    match bare {
+        true => out.push_str(name),
+        false => write_string(out, name),
+    }
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::String(s) => write_string(out, s),
+        Value::Int(i) => drop(write!(out, "{i}")),
+        Value::Float(f) => drop(write!(out, "{f:?}")),
+        Value::Bool(b) => drop(write!(out, "{b}")),
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if c.is_control() => drop(write!(out, "\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_strings_and_identifiers() {
+        let node = Node::new("spawn-at-startup")
+            .arg("sh")
+            .arg("-c")
+            .arg("echo \"hi\"\\n\tdone")
+            .child(
+                Node::new("needs quoting")
+                    .prop("match=", 1.0)
+                    .prop("on", true),
+            );
+
+        assert_eq!(
+            document(&[node, Node::new("1st").arg(-6)]),
+            "spawn-at-startup \"sh\" \"-c\" \"echo \\\"hi\\\"\\\\n\\tdone\" {\n    \
+             \"needs quoting\" \"match=\"=1.0 on=true\n}\n\n\"1st\" -6\n"
+        );
+    }
+}
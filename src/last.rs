@@ -0,0 +1,120 @@
+use std::{
+    io::{ErrorKind, Write},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::log;
+
+use super::{Error, Result};
+
+pub const LAST_PATH: &str = "/var/lib/rilm/last.toml";
+
+pub const USER_ENV: &str = "RILM_LAST_USER";
+pub const SESSION_ENV: &str = "RILM_LAST_SESSION";
+pub const LAYOUT_ENV: &str = "RILM_LAST_LAYOUT";
+
+/// What the greeter is pre-filled with, from the last successful login.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Last {
+    pub user: Option<String>,
+    /// Desktop session id, see [`crate::sessions::Session::id`].
+    pub session: Option<String>,
+    pub layout: Option<String>,
+}
+
+impl Last {
+    /// Nothing is remembered when the file is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                log::warning!("Ignoring {}: {e}", path.display());
+                Self::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                log::warning!("Ignoring {}: {e}", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    /// Replace the file at `path` at once, so that a crash never leaves it
+    /// half written. It stays readable by the greeter, only root can change it.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = path
+            .parent()
+            .ok_or(Error::Config(format!("{} has no parent", path.display())))?;
+
+        std::fs::create_dir_all(dir)?;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o755))?;
+
+        let contents = toml::to_string(self).map_err(|e| Error::Config(e.to_string()))?;
+
+        let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+        tmp.write_all(contents.as_bytes())?;
+        tmp.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(path).map_err(|e| Error::IoError(e.error))?;
+
+        Ok(())
+    }
+
+    /// For shared machines, where nobody should see who logged in before.
+    pub fn forget(path: &Path) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// What the greeter is started with, as `(KEY, VALUE)`.
+    pub fn env(&self) -> Vec<(&'static str, &str)> {
+        [
+            (USER_ENV, &self.user),
+            (SESSION_ENV, &self.session),
+            (LAYOUT_ENV, &self.layout),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value.as_deref()?)))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[test]
+    fn saves_atomically_and_reads_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rilm/last.toml");
+
+        assert_eq!(Last::load(&path), Last::default());
+
+        let last = Last {
+            user: Some(String::from("alice")),
+            session: Some(String::from("sway")),
+            layout: None,
+        };
+        last.save(&path).unwrap();
+
+        assert_eq!(Last::load(&path), last);
+        assert_eq!(std::fs::metadata(&path).unwrap().mode() & 0o777, 0o644);
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
+        assert_eq!(last.env(), [(USER_ENV, "alice"), (SESSION_ENV, "sway")]);
+
+        Last::forget(&path).unwrap();
+        Last::forget(&path).unwrap();
+        assert!(!path.exists());
+    }
+}
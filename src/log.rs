@@ -0,0 +1,367 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{ErrorKind, Write},
+    os::{
+        fd::AsFd,
+        unix::{fs::OpenOptionsExt, net::UnixDatagram},
+    },
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+use super::Result;
+
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const IDENTIFIER: &str = "rilm";
+const LOG_FILE: &str = "rilm.log";
+/// Size at which the log file is moved aside for a new one.
+const MAX_FILE_SIZE: u64 = 1 << 20;
+
+/// Fields every entry carries while they are set.
+pub const USER: &str = "RILM_USER";
+pub const SESSION_ID: &str = "RILM_SESSION_ID";
+pub const STATE: &str = "RILM_STATE";
+
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Error, &format!($($arg)*)) };
+}
+macro_rules! warning {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Warning, &format!($($arg)*)) };
+}
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Info, &format!($($arg)*)) };
+}
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::write($crate::log::Level::Debug, &format!($($arg)*)) };
+}
+pub(crate) use {debug, error, info, warning};
+
+/// syslog's levels, from the most to the least important.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Level {
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn priority(self) -> u8 {
+        3 + self as u8
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Notice => write!(f, "notice"),
+            Self::Info => write!(f, "info"),
+            Self::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+/// Where entries go.
+pub enum Sink {
+    /// journald's native protocol, one datagram per entry.
+    Journal(UnixDatagram),
+    File(PathBuf, File),
+    Stderr,
+}
+
+impl Sink {
+    pub fn journal(socket: &Path) -> Option<Self> {
+        let datagram = UnixDatagram::unbound().ok()?;
+        datagram.connect(socket).ok()?;
+
+        Some(Self::Journal(datagram))
+    }
+
+    /// Appending to `dir/rilm.log`, `None` when it can't be written.
+    pub fn file(dir: &Path) -> Option<Self> {
+        let path = dir.join(LOG_FILE);
+
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            if e.kind() != ErrorKind::PermissionDenied {
+                eprintln!("Couldn't create {}: {e}", dir.display());
+            }
+            return None;
+        }
+
+        Some(Self::File(path.clone(), open_log(&path).ok()?))
+    }
+}
+
+pub struct Logger {
+    level: Level,
+    sink: Mutex<Sink>,
+    context: Mutex<Vec<(&'static str, String)>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+impl Logger {
+    pub fn new(level: Level, sink: Sink) -> Self {
+        Self {
+            level,
+            sink: Mutex::new(sink),
+            context: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn log(&self, level: Level, message: &str) {
+        if level > self.level {
+            return;
+        }
+
+        let context = self
+            .context
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+
+        let written = match &mut *sink {
+            Sink::Journal(socket) => socket
+                .send(&journal_entry(level, message, &context))
+                .map(drop),
+            Sink::File(path, file) => {
+                if file.metadata().is_ok_and(|meta| meta.len() > MAX_FILE_SIZE) {
+                    let _ = std::fs::rename(&*path, path.with_extension("log.1"));
+                    if let Ok(reopened) = open_log(path) {
+                        *file = reopened;
+                    }
+                }
+
+                file.write_all(line(level, message, &context, true).as_bytes())
+            }
+            Sink::Stderr => {
+                eprint!("{}", line(level, message, &context, false));
+                Ok(())
+            }
+        };
+
+        if let Err(e) = written {
+            eprint!("{}", line(level, message, &context, false));
+            eprintln!("(couldn't log it: {e})");
+        }
+    }
+
+    /// Set `key` for the entries that follow, or remove it with `None`.
+    pub fn set(&self, key: &'static str, value: Option<&str>) {
+        let mut context = self.context.lock().unwrap_or_else(|e| e.into_inner());
+
+        context.retain(|(k, _)| *k != key);
+        if let Some(value) = value {
+            context.push((key, value.to_string()));
+        }
+    }
+}
+
+/// Send this process' entries to journald when it runs, to `config.dir`
+/// otherwise, and to stderr when neither works.
+pub fn init(config: &config::Log) {
+    let sink = config
+        .journal
+        .then(|| Sink::journal(Path::new(JOURNAL_SOCKET)))
+        .flatten()
+        .or_else(|| Sink::file(&config.dir))
+        .unwrap_or(Sink::Stderr);
+
+    let _ = LOGGER.set(Logger::new(config.level, sink));
+}
+
+/// Log to what [`init`] set up, to stderr before that.
+pub fn write(level: Level, message: &str) {
+    match LOGGER.get() {
+        Some(logger) => logger.log(level, message),
+        None => eprintln!("{message}"),
+    }
+}
+
+/// See [`Logger::set`].
+pub fn set(key: &'static str, value: Option<&str>) {
+    if let Some(logger) = LOGGER.get() {
+        logger.set(key, value);
+    }
+}
+
+/// `KEY=VALUE` lines, values with a newline in them length-prefixed.
+pub fn journal_entry(level: Level, message: &str, context: &[(&'static str, String)]) -> Vec<u8> {
+    let mut entry = Vec::new();
+
+    let fields = [
+        ("PRIORITY", level.priority().to_string()),
+        ("SYSLOG_IDENTIFIER", String::from(IDENTIFIER)),
+        ("MESSAGE", message.to_string()),
+    ];
+
+    for (key, value) in fields.iter().chain(context) {
+        entry.extend_from_slice(key.as_bytes());
+
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+
+    entry
+}
+
+fn line(
+    level: Level,
+    message: &str,
+    context: &[(&'static str, String)],
+    timestamp: bool,
+) -> String {
+    let mut line = String::new();
+
+    if timestamp {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        line.push_str(&format!("{}.{:03} ", now.as_secs(), now.subsec_millis()));
+    }
+
+    line.push_str(&format!("{level}: {message}"));
+
+    for (key, value) in context {
+        line.push_str(&format!(" {key}={value}"));
+    }
+
+    line.push('\n');
+    line
+}
+
+fn open_log(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o640)
+        .open(path)
+}
+
+/// Where the output of the current user's sessions is kept.
+pub fn session_dir(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local/state"))
+        .join("rilm")
+}
+
+/// A new `<name>-1.log` in `dir`, the ones before it moved one number up
+/// and the oldest past `keep` removed.
+pub fn rotate(dir: &Path, name: &str, keep: usize) -> Result<(PathBuf, File)> {
+    std::fs::create_dir_all(dir)?;
+
+    let path = |n: usize| dir.join(format!("{name}-{n}.log"));
+
+    match std::fs::remove_file(path(keep)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+
+    for n in (1..keep).rev() {
+        match std::fs::rename(path(n), path(n + 1)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path(1))?;
+
+    Ok((path(1), file))
+}
+
+/// Send this process' stdout and stderr, and so what it execs, to a new
+/// log in `dir`, see [`rotate`].
+pub fn capture(dir: &Path, name: &str, keep: usize) -> Result<PathBuf> {
+    let (path, file) = rotate(dir, name, keep)?;
+
+    nix::unistd::dup2_stdout(file.as_fd())?;
+    nix::unistd::dup2_stderr(file.as_fd())?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_to_the_journal_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("socket");
+        let journal = UnixDatagram::bind(&socket).unwrap();
+
+        let logger = Logger::new(Level::Info, Sink::journal(&socket).unwrap());
+        logger.set(USER, Some("erin"));
+        logger.set(STATE, Some("greeter"));
+        logger.set(STATE, Some("session opened"));
+
+        logger.log(Level::Debug, "filtered out");
+        logger.log(Level::Warning, "two\nlines");
+
+        let mut buffer = [0; 1024];
+        let len = journal.recv(&mut buffer).unwrap();
+
+        let mut expected = b"PRIORITY=4\nSYSLOG_IDENTIFIER=rilm\nMESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\nRILM_USER=erin\nRILM_STATE=session opened\n");
+        assert_eq!(buffer[..len], expected);
+
+        journal.set_nonblocking(true).unwrap();
+        assert!(journal.recv(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(Sink::journal(&dir.path().join("missing")).is_none());
+
+        let logger = Logger::new(Level::Info, Sink::file(&dir.path().join("log")).unwrap());
+        logger.set(SESSION_ID, Some("3"));
+        logger.log(Level::Error, "it broke");
+
+        let contents = std::fs::read_to_string(dir.path().join("log/rilm.log")).unwrap();
+        assert!(contents.ends_with(" error: it broke RILM_SESSION_ID=3\n"));
+    }
+
+    #[test]
+    fn rotates_session_logs() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for n in 1..=4 {
+            let (_, mut file) = rotate(dir.path(), "session", 3).unwrap();
+            write!(file, "{n}").unwrap();
+        }
+
+        let read = |n| std::fs::read_to_string(dir.path().join(format!("session-{n}.log")));
+        assert_eq!(read(1).unwrap(), "4");
+        assert_eq!(read(2).unwrap(), "3");
+        assert_eq!(read(3).unwrap(), "2");
+        assert!(read(4).is_err());
+    }
+}
@@ -1,11 +1,37 @@
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
 use clap::{Parser, Subcommand};
 use error::*;
 
 mod error;
 
+mod auth;
 mod config;
+mod console;
+mod environment;
+mod greetd;
+mod greeter;
+mod install;
+mod ipc;
+mod kdl;
+mod last;
+mod log;
 mod niri;
+mod notify;
+mod prompt;
+mod scope;
+mod sessions;
+mod signals;
+mod spawn;
 mod steps;
+mod supervisor;
+mod systemd;
+mod sysuser;
+mod utmp;
+mod xwayland;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -23,8 +49,43 @@ struct Cli {
 enum Command {
     /// Start rilm
     Start(StartArgs),
+    /// Inspect rilm configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Print the environment a user's session would start with, PAM aside
+    Env {
+        /// User to build it for (optional, defaults to current user)
+        #[arg(long)]
+        user: Option<String>,
+    },
     /// Patch rilm configuration (may need sudo)
-    PatchConfig,
+    PatchConfig {
+        /// Print what would change as a unified diff, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Install under this directory instead of /, for staging
+        #[arg(long, default_value = "/")]
+        root: PathBuf,
+
+        /// Restore the files that were there before rilm patched them
+        #[arg(long)]
+        uninstall: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the effective configuration, defaults and drop-ins included
+    Dump,
+    /// Write the built-in niri session config to ~/.config/niri/config.kdl
+    InitUser {
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Parser, Debug)]
@@ -38,6 +99,11 @@ struct StartArgs {
 enum StartTarget {
     /// Start display manager
     Display {
+        /// Greeter program to run instead of the built-in prompt, e.g. "tuigreet"
+        /// (it can talk to rilm through GREETD_SOCK)
+        #[arg(long)]
+        greeter: Option<String>,
+
         /// Display mode
         #[command(subcommand)]
         mode: DisplayMode,
@@ -51,25 +117,45 @@ enum StartTarget {
         /// If set, will launch a prompt asking for credentials
         #[arg(long)]
         prompt: bool,
+
+        /// Greeter program to run instead of the built-in prompt
+        #[arg(last = true)]
+        command: Vec<String>,
     },
     /// Start session (will use current user if --user not specified)
     Session {
         /// Username for session (optional, defaults to current user)
         #[arg(long)]
         user: Option<String>,
+
+        /// Session command to run instead of the built-in niri session
+        #[arg(last = true)]
+        command: Vec<String>,
+
+        /// Run the session itself, the systemd user manager is taken care of
+        #[arg(long, hide = true)]
+        managed: bool,
+    },
+    /// Supervise xwayland-satellite for the session about to start
+    #[command(hide = true)]
+    Xwayland {
+        /// X display number to serve
+        #[arg(long)]
+        display: u32,
+
+        /// Wayland sockets that were there before the compositor started
+        #[arg(long)]
+        existing: Vec<String>,
     },
 }
 
 #[derive(Subcommand, Debug)]
 enum DisplayMode {
-    /// Start display in TTY mode (assumes root, runs everything on tty number, default = 1)
+    /// Start display in TTY mode (assumes root, runs everything on tty number, default from config)
     Tty {
         /// TTY number (must be >= 1)
-        #[arg(
-            default_value_t = 1,
-            value_parser = validate_tty_number
-        )]
-        tty_number: u16,
+        #[arg(value_parser = validate_tty_number)]
+        tty_number: Option<u16>,
     },
     /// Start display in Winit mode (run everything in a simulated window under the current user)
     Winit,
@@ -89,40 +175,90 @@ fn cli() -> Result<()> {
     use steps::*;
 
     let cli = Cli::parse();
+    // The display loads it itself, falling back on the defaults.
+    let config = config::Config::load;
 
     match cli.command {
         Some(Command::Start(start_args)) => match start_args.target {
-            StartTarget::Display { mode } => match mode {
-                DisplayMode::Tty { tty_number } => start_display_tty(tty_number),
-                DisplayMode::Winit => start_display_winit(),
-            },
-            StartTarget::Greeter { user, prompt } => {
+            StartTarget::Display { greeter, mode } => {
+                let load: Loader = Box::new(move || {
+                    let mut config = config()?;
+                    if let Some(greeter) = &greeter {
+                        config.greeter.command =
+                            greeter.split_whitespace().map(String::from).collect();
+                    }
+                    Ok(config)
+                });
+
+                match mode {
+                    DisplayMode::Tty { tty_number } => start_display_tty(tty_number, load),
+                    DisplayMode::Winit => start_display_winit(load),
+                }
+            }
+            StartTarget::Greeter {
+                user,
+                prompt,
+                command,
+            } => {
                 if prompt {
                     start_greeter_prompt()
                 } else {
-                    start_greeter(user)
+                    start_greeter(user, command, &config()?)
                 }
             }
-            StartTarget::Session { user } => start_session(user),
+            StartTarget::Session {
+                user,
+                command,
+                managed,
+            } => start_session(user, command, managed, &config()?),
+            StartTarget::Xwayland { display, existing } => {
+                start_xwayland_supervisor(display, existing, &config()?)
+            }
         },
-        Some(Command::PatchConfig) => patch_config(),
-        None => start_display_tty(1),
+        Some(Command::PatchConfig {
+            dry_run,
+            root,
+            uninstall,
+        }) => patch_config(
+            install::Options {
+                root,
+                dry_run,
+                uninstall,
+            },
+            &config()?,
+        ),
+        Some(Command::Config {
+            action: ConfigAction::Dump,
+        }) => {
+            print!("{}", config()?.dump()?);
+            Ok(())
+        }
+        Some(Command::Config {
+            action: ConfigAction::InitUser { force },
+        }) => {
+            let home = std::env::var_os("HOME").ok_or(Error::MissingEnv("HOME"))?;
+            let path = config()?.init_user(Path::new(&home), force)?;
+            println!("Wrote {}", path.display());
+            Ok(())
+        }
+        Some(Command::Env { user }) => print_env(user, &config()?),
+        None => start_display_tty(None, Box::new(config)),
     }
 }
 
-fn main() {
-    if let Err(e) = cli() {
-        eprintln!("{e}");
+fn main() -> ExitCode {
+    match cli() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn patch_config() -> Result<()> {
+fn patch_config(options: install::Options, config: &config::Config) -> Result<()> {
     println!("Patching RILM configuration (may require sudo)");
 
-    todo!(
-        r#"
-            - Patch pam.d service
-            - Patch systemctl service
-        "#
-    )
+    let exe = std::env::current_exe()?;
+    install::run(&install::artifacts(&exe, config), &options)
 }
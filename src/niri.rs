@@ -1,28 +1,645 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{
+    config,
+    kdl::{self, Node, Value},
+    log,
+};
+
+use super::Result;
+
 macro_rules! launch {
-    ($config:expr, $($arg:expr),* $(,)?) => {{
-        use std::{io::Write, os::unix::ffi::OsStrExt};
-
-        let mut tmp = tempfile::NamedTempFile::new().map_err(Error::IoError)?;
-        tmp.write_all($config.as_bytes()).map_err(Error::IoError)?;
-
-        let (niri, c, config, dash, alacritty, e) = try_cstrings!(
-            "/usr/bin/niri",
-            "-c",
-            tmp.path().as_os_str().as_bytes(),
-            "--",
-            "/usr/bin/alacritty",
-            "-e",
-        )
-        .map_err(Error::NulError)?;
+    ($config:expr, $programs:expr, $($arg:expr),* $(,)?) => {
+        niri::launch!($config, $programs; [$(String::from($arg)),*])
+    };
+    ($config:expr, $programs:expr; $args:expr) => {
+        niri::launch!($config, $programs, &[]; $args)
+    };
+    ($config:expr, $programs:expr, $flags:expr; $args:expr) => {{
+        use std::os::unix::ffi::OsStrExt;
 
-        let args = [
-            &niri, &c, &config, &dash, &alacritty, &e,
-            $(&std::ffi::CString::new($arg)?,)*
-        ];
+        let programs: &crate::config::Programs = $programs;
+
+        let (tmp, notice) = niri::prepare(&$config, &programs.niri)?;
 
-        if nix::unistd::execv::<&std::ffi::CString>(&niri, &args).is_err() {
-            std::process::exit(1);
+        let mut env = std::env::vars_os()
+            .filter(|(key, _)| key != niri::NOTICE_ENV)
+            .map(|(key, val)| {
+                let mut var = key;
+                var.push("=");
+                var.push(val);
+                std::ffi::CString::new(var.as_bytes())
+            })
+            .collect::<core::result::Result<Vec<_>, _>>()?;
+
+        if let Some(notice) = notice {
+            env.push(std::ffi::CString::new(notice)?);
         }
+
+        let flags: &[&str] = $flags;
+
+        Err(crate::spawn::Spec::new(&programs.niri)
+            .args(flags)
+            .arg("-c")
+            .arg(tmp.path())
+            .arg("--")
+            .args(programs.terminal.iter().chain($args.iter()))
+            .env(env)
+            .exec())
     }};
 }
 pub(crate) use launch;
+
+/// Set for the greeter when its niri config was replaced by the safe one.
+pub const NOTICE_ENV: &str = "RILM_NOTICE";
+
+/// `config` in a file niri accepts, or the safe config in its place along
+/// with the [`NOTICE_ENV`] assignment that tells the user.
+pub fn prepare(config: &str, niri: &Path) -> Result<(NamedTempFile, Option<String>)> {
+    let mut tmp = NamedTempFile::new()?;
+    tmp.write_all(config.as_bytes())?;
+
+    if let Err(diagnostics) = validate(niri, tmp.path()) {
+        log::warning!(
+            "The niri config is invalid, starting with a safe one instead:\n{diagnostics}"
+        );
+
+        tmp = NamedTempFile::new()?;
+        tmp.write_all(Config::safe().to_kdl().as_bytes())?;
+
+        return Ok((
+            tmp,
+            Some(format!(
+                "{NOTICE_ENV}=The niri configuration is invalid, a minimal one is used. See the logs for details."
+            )),
+        ));
+    }
+
+    Ok((tmp, None))
+}
+
+/// Where niri looks for the config of the user whose home is `home`.
+pub fn user_config(home: &Path) -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"))
+        .join("niri/config.kdl")
+}
+
+/// The user's own config at `path`, followed by what rilm needs in every
+/// session so that it takes precedence.
+pub fn overlay(config: &config::Config, path: &Path) -> String {
+    let mut nodes = vec![Node::new("include").arg(path.display().to_string())];
+
+    nodes.extend(
+        Config::base(config)
+            .spawn_at_startup
+            .iter()
+            .map(|command| Node::new("spawn-at-startup").args(command)),
+    );
+
+    nodes.push(Node::new("binds").children([
+        Bind::spawn("Super+Alt+L", &["loginctl", "lock-session"]).to_node(),
+        Bind::new("Mod+Shift+E", "quit").to_node(),
+    ]));
+
+    kdl::document(&nodes)
+}
+
+/// Ask niri whether it accepts the config at `path`, `Err` with its
+/// diagnostics if not. A niri that can't be run is left for exec to report.
+pub fn validate(niri: &Path, path: &Path) -> core::result::Result<(), String> {
+    match Command::new(niri)
+        .arg("validate")
+        .arg("-c")
+        .arg(path)
+        .output()
+    {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .trim()
+        .to_string()),
+        Err(e) => {
+            log::warning!("Couldn't validate the niri config: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// The parts of niri's configuration rilm generates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub prefer_no_csd: bool,
+    pub screenshot_path: Option<String>,
+    pub skip_hotkey_overlay: bool,
+    pub spawn_at_startup: Vec<Vec<String>>,
+    pub input: Input,
+    pub outputs: Vec<Output>,
+    pub layout: Layout,
+    pub window_rules: Vec<WindowRule>,
+    pub binds: Vec<Bind>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    pub xkb: Xkb,
+    pub touchpad: Touchpad,
+}
+
+/// Unset fields are left to niri, which then follows the system keymap.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Xkb {
+    pub layout: Option<String>,
+    pub variant: Option<String>,
+    pub options: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Touchpad {
+    pub tap: bool,
+    pub natural_scroll: bool,
+    pub dwt: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Output {
+    pub name: String,
+    pub mode: Option<String>,
+    pub scale: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub gaps: u16,
+    pub struts: i32,
+    pub center_focused_column: String,
+    pub focus_ring_width: u16,
+    pub focus_ring_from: String,
+    pub focus_ring_to: String,
+    pub shadow: bool,
+    pub default_column_width: f64,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            gaps: 10,
+            struts: -6,
+            center_focused_column: String::from("never"),
+            focus_ring_width: 4,
+            focus_ring_from: String::from("#efc05d"),
+            focus_ring_to: String::from("#161d43"),
+            shadow: true,
+            default_column_width: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowRule {
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    pub open_floating: Option<bool>,
+    pub default_column_width: Option<f64>,
+    pub geometry_corner_radius: Option<u16>,
+    pub clip_to_geometry: Option<bool>,
+    pub min_width: Option<u16>,
+    pub min_height: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bind {
+    pub key: String,
+    pub allow_when_locked: bool,
+    pub action: String,
+    pub args: Vec<Value>,
+}
+
+impl Bind {
+    pub fn new(key: impl Into<String>, action: &str) -> Self {
+        Self {
+            key: key.into(),
+            allow_when_locked: false,
+            action: action.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, value: impl Into<Value>) -> Self {
+        self.args.push(value.into());
+        self
+    }
+
+    pub fn spawn(key: impl Into<String>, command: &[&str]) -> Self {
+        Self {
+            args: command.iter().map(|&arg| arg.into()).collect(),
+            ..Self::new(key, "spawn")
+        }
+    }
+
+    fn when_locked(mut self) -> Self {
+        self.allow_when_locked = true;
+        self
+    }
+}
+
+impl Config {
+    /// What every rilm niri instance shares.
+    fn base(config: &config::Config) -> Self {
+        let niri = &config.niri;
+
+        Self {
+            spawn_at_startup: vec![vec![
+                String::from("swaybg"),
+                String::from("-i"),
+                config.display.wallpaper.display().to_string(),
+            ]],
+            input: Input {
+                xkb: Xkb {
+                    layout: niri.keyboard.layout.clone(),
+                    variant: niri.keyboard.variant.clone(),
+                    options: niri.keyboard.options.clone(),
+                    model: niri.keyboard.model.clone(),
+                },
+                touchpad: Touchpad {
+                    tap: niri.touchpad.tap,
+                    natural_scroll: niri.touchpad.natural_scroll,
+                    dwt: niri.touchpad.dwt,
+                },
+            },
+            outputs: niri
+                .outputs
+                .iter()
+                .map(|output| Output {
+                    name: output.name.clone(),
+                    mode: output.mode.clone(),
+                    scale: output.scale,
+                })
+                .collect(),
+            layout: Layout::default(),
+            window_rules: vec![WindowRule {
+                geometry_corner_radius: Some(10),
+                clip_to_geometry: Some(true),
+                min_width: Some(300),
+                min_height: Some(300),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// Nothing that depends on the machine or on rilm's config, for when the
+    /// generated config is refused.
+    pub fn safe() -> Self {
+        Self {
+            skip_hotkey_overlay: true,
+            binds: vec![Bind::new("Mod+Shift+E", "quit")],
+            ..Default::default()
+        }
+    }
+
+    pub fn greeter(config: &config::Config) -> Self {
+        Self {
+            skip_hotkey_overlay: true,
+            binds: vec![Bind::new("Mod+Shift+E", "quit")],
+            ..Self::base(config)
+        }
+    }
+
+    /// The built-in session, screenshots go to the user's `home`.
+    pub fn session(config: &config::Config, home: Option<&Path>) -> Self {
+        let niri = &config.niri;
+        let mut base = Self::base(config);
+
+        base.window_rules.extend([
+            WindowRule {
+                app_id: Some(String::from("firefox$")),
+                title: Some(String::from("^Picture-in-Picture$")),
+                open_floating: Some(true),
+                ..Default::default()
+            },
+            WindowRule {
+                app_id: Some(String::from("Alacritty$")),
+                default_column_width: Some(0.5),
+                ..Default::default()
+            },
+        ]);
+
+        let mut binds = vec![
+            Bind::new("Mod+Shift+D", "show-hotkey-overlay"),
+            Bind::new("Mod+Left", "focus-column-or-monitor-left"),
+            Bind::new("Mod+Down", "focus-window-or-workspace-down"),
+            Bind::new("Mod+Up", "focus-window-or-workspace-up"),
+            Bind::new("Mod+Right", "focus-column-or-monitor-right"),
+            Bind::new("Mod+Shift+Down", "move-window-down-or-to-workspace-down"),
+            Bind::new("Mod+Shift+Up", "move-window-up-or-to-workspace-up"),
+            Bind::new("Mod+Shift+Left", "consume-or-expel-window-left"),
+            Bind::new("Mod+Shift+Right", "consume-or-expel-window-right"),
+        ];
+
+        for (i, key) in niri.workspace_keys.iter().enumerate() {
+            binds.push(Bind::new(format!("Mod+{key}"), "focus-workspace").arg(i as i64 + 1));
+        }
+        for (i, key) in niri.workspace_keys.iter().enumerate() {
+            binds.push(
+                Bind::new(format!("Mod+Shift+{key}"), "move-window-to-workspace").arg(i as i64 + 1),
+            );
+        }
+
+        binds.extend([
+            Bind::new("Alt+Ctrl+Left", "set-window-width").arg("-10%"),
+            Bind::new("Alt+Ctrl+Right", "set-window-width").arg("+10%"),
+            Bind::new("Alt+Ctrl+Up", "set-window-height").arg("-10%"),
+            Bind::new("Alt+Ctrl+Down", "set-window-height").arg("+10%"),
+            Bind::spawn(
+                "XF86AudioRaiseVolume",
+                &["wpctl", "set-volume", "@DEFAULT_AUDIO_SINK@", "0.1+"],
+            )
+            .when_locked(),
+            Bind::spawn(
+                "XF86AudioLowerVolume",
+                &["wpctl", "set-volume", "@DEFAULT_AUDIO_SINK@", "0.1-"],
+            )
+            .when_locked(),
+            Bind::spawn(
+                "XF86AudioMute",
+                &["wpctl", "set-mute", "@DEFAULT_AUDIO_SINK@", "toggle"],
+            )
+            .when_locked(),
+            Bind::spawn(
+                "XF86AudioMicMute",
+                &["wpctl", "set-mute", "@DEFAULT_AUDIO_SOURCE@", "toggle"],
+            )
+            .when_locked(),
+            Bind::spawn("XF86MonBrightnessUp", &["brightnessctl", "s", "10%+"]).when_locked(),
+            Bind::spawn("XF86MonBrightnessDown", &["brightnessctl", "s", "10%-"]).when_locked(),
+            Bind::new("Mod+R", "maximize-column"),
+            Bind::new("Mod+F", "fullscreen-window"),
+            Bind::new("Mod+Shift+A", "close-window"),
+            Bind::spawn("Mod+Return", &[niri.terminal.as_str()]),
+            Bind::spawn("Mod+D", &[niri.launcher.as_str()]),
+            Bind::new("Mod+Shift+E", "quit"),
+            Bind::new("Mod+Shift+P", "power-off-monitors"),
+            Bind::new("Print", "screenshot"),
+            Bind::new("Ctrl+Print", "screenshot-screen"),
+            Bind::new("Alt+Print", "screenshot-window"),
+        ]);
+
+        Self {
+            prefer_no_csd: true,
+            screenshot_path: home.map(|home| {
+                home.join("Pictures/Screenshots/Screenshot from %Y-%m-%d %H-%M-%S.png")
+                    .display()
+                    .to_string()
+            }),
+            binds,
+            ..base
+        }
+    }
+
+    pub fn to_kdl(&self) -> String {
+        let mut nodes = Vec::new();
+
+        if self.prefer_no_csd {
+            nodes.push(Node::new("prefer-no-csd"));
+        }
+
+        if let Some(path) = &self.screenshot_path {
+            nodes.push(Node::new("screenshot-path").arg(path));
+        }
+
+        for command in &self.spawn_at_startup {
+            nodes.push(Node::new("spawn-at-startup").args(command));
+        }
+
+        if self.skip_hotkey_overlay {
+            nodes.push(Node::new("hotkey-overlay").child(Node::new("skip-at-startup")));
+        }
+
+        nodes.push(self.input.to_node());
+        nodes.extend(self.outputs.iter().map(Output::to_node));
+        nodes.push(self.layout.to_node());
+        nodes.extend(self.window_rules.iter().map(WindowRule::to_node));
+
+        if !self.binds.is_empty() {
+            nodes.push(Node::new("binds").children(self.binds.iter().map(Bind::to_node)));
+        }
+
+        kdl::document(&nodes)
+    }
+}
+
+impl Input {
+    fn to_node(&self) -> Node {
+        let xkb = [
+            ("layout", &self.xkb.layout),
+            ("variant", &self.xkb.variant),
+            ("options", &self.xkb.options),
+            ("model", &self.xkb.model),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some(Node::new(name).arg(value.as_ref()?)));
+
+        let touchpad = [
+            ("tap", self.touchpad.tap),
+            ("natural-scroll", self.touchpad.natural_scroll),
+            ("dwt", self.touchpad.dwt),
+        ]
+        .into_iter()
+        .filter(|(_, on)| *on)
+        .map(|(name, _)| Node::new(name));
+
+        Node::new("input")
+            .child(Node::new("keyboard").child(Node::new("xkb").children(xkb)))
+            .child(Node::new("touchpad").children(touchpad))
+    }
+}
+
+impl Output {
+    fn to_node(&self) -> Node {
+        let mut node = Node::new("output").arg(&self.name);
+
+        if let Some(mode) = &self.mode {
+            node = node.child(Node::new("mode").arg(mode));
+        }
+        if let Some(scale) = self.scale {
+            node = node.child(Node::new("scale").arg(scale));
+        }
+
+        node
+    }
+}
+
+impl Layout {
+    fn to_node(&self) -> Node {
+        let struts =
+            ["left", "right", "top", "bottom"].map(|side| Node::new(side).arg(self.struts));
+
+        Node::new("layout")
+            .child(Node::new("gaps").arg(self.gaps))
+            .child(Node::new("struts").children(struts))
+            .child(Node::new("center-focused-column").arg(&self.center_focused_column))
+            .child(
+                Node::new("focus-ring")
+                    .child(Node::new("width").arg(self.focus_ring_width))
+                    .child(
+                        Node::new("active-gradient")
+                            .prop("from", &self.focus_ring_from)
+                            .prop("to", &self.focus_ring_to)
+                            .prop("angle", 45)
+                            .prop("relative-to", "workspace-view"),
+                    ),
+            )
+            .child(Node::new("border").child(Node::new("off")))
+            .child(Node::new("shadow").child(Node::new(match self.shadow {
+                true => "on",
+                false => "off",
+            })))
+            .child(
+                Node::new("default-column-width")
+                    .child(Node::new("proportion").arg(self.default_column_width)),
+            )
+    }
+}
+
+impl WindowRule {
+    fn to_node(&self) -> Node {
+        let mut node = Node::new("window-rule");
+
+        if self.app_id.is_some() || self.title.is_some() {
+            let mut matcher = Node::new("match");
+            if let Some(app_id) = &self.app_id {
+                matcher = matcher.prop("app-id", app_id);
+            }
+            if let Some(title) = &self.title {
+                matcher = matcher.prop("title", title);
+            }
+            node = node.child(matcher);
+        }
+
+        if let Some(radius) = self.geometry_corner_radius {
+            node = node.child(Node::new("geometry-corner-radius").arg(radius));
+        }
+        if let Some(clip) = self.clip_to_geometry {
+            node = node.child(Node::new("clip-to-geometry").arg(clip));
+        }
+        if let Some(width) = self.min_width {
+            node = node.child(Node::new("min-width").arg(width));
+        }
+        if let Some(height) = self.min_height {
+            node = node.child(Node::new("min-height").arg(height));
+        }
+        if let Some(floating) = self.open_floating {
+            node = node.child(Node::new("open-floating").arg(floating));
+        }
+        if let Some(proportion) = self.default_column_width {
+            node = node.child(
+                Node::new("default-column-width").child(Node::new("proportion").arg(proportion)),
+            );
+        }
+
+        node
+    }
+}
+
+impl Bind {
+    fn to_node(&self) -> Node {
+        let mut node = Node::new(&self.key);
+
+        if self.allow_when_locked {
+            node = node.prop("allow-when-locked", true);
+        }
+
+        node.child(Node::new(&self.action).args(self.args.iter().cloned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compare with the file under `resources/golden`, or rewrite it when
+    /// `RILM_UPDATE_GOLDEN` is set.
+    fn golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/golden")
+            .join(name);
+
+        if std::env::var_os("RILM_UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, actual).unwrap();
+        }
+
+        assert_eq!(actual, std::fs::read_to_string(&path).unwrap(), "{name}");
+    }
+
+    #[test]
+    fn validate_reports_diagnostics() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("niri");
+        std::fs::write(
+            &stub,
+            "#!/bin/sh\n[ \"$1 $2 $3\" = \"validate -c good.kdl\" ] && exit 0\necho \"error: unexpected node\" >&2\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&stub, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(validate(&stub, Path::new("good.kdl")), Ok(()));
+        assert_eq!(
+            validate(&stub, Path::new("bad.kdl")),
+            Err(String::from("error: unexpected node"))
+        );
+        assert_eq!(
+            validate(&dir.path().join("missing"), Path::new("bad.kdl")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn greeter_matches_golden() {
+        golden(
+            "niri-greeter.kdl",
+            &Config::greeter(&config::Config::default()).to_kdl(),
+        );
+    }
+
+    #[test]
+    fn session_matches_golden() {
+        golden(
+            "niri-session.kdl",
+            &Config::session(&config::Config::default(), Some(Path::new("/home/user"))).to_kdl(),
+        );
+    }
+
+    #[test]
+    fn configured_keyboard_and_outputs_matches_golden() {
+        let mut config = config::Config::default();
+        config.niri.keyboard.layout = Some(String::from("fr"));
+        config.niri.keyboard.variant = Some(String::from("mac"));
+        config.niri.touchpad.natural_scroll = false;
+        config.niri.outputs.push(config::Output {
+            name: String::from("eDP-1"),
+            mode: Some(String::from("1920x1080@60.042")),
+            scale: Some(1.25),
+        });
+        config.display.wallpaper = PathBuf::from("/srv/walls/\"quoted\".png");
+
+        golden(
+            "niri-greeter-configured.kdl",
+            &Config::greeter(&config).to_kdl(),
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    os::{
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixDatagram},
+    },
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use nix::poll::PollTimeout;
+
+use crate::log;
+
+use super::Result;
+
+pub const SOCKET_ENV: &str = "NOTIFY_SOCKET";
+pub const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+pub const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+
+/// The service manager that started rilm, told about its state over the
+/// sd_notify protocol.
+pub struct Notifier {
+    socket: UnixDatagram,
+    /// Half the watchdog timeout, so that a ping is never late.
+    watchdog: Option<Duration>,
+    last_ping: Mutex<Instant>,
+}
+
+static NOTIFIER: OnceLock<Option<Notifier>> = OnceLock::new();
+
+impl Notifier {
+    /// `path` starting with `@` is in the abstract namespace.
+    pub fn connect(path: &str, watchdog: Option<Duration>) -> Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+
+        match path.strip_prefix('@') {
+            Some(name) => socket.connect_addr(&SocketAddr::from_abstract_name(name)?)?,
+            None => socket.connect(path)?,
+        }
+
+        Ok(Self {
+            socket,
+            watchdog: watchdog.map(|timeout| timeout / 2),
+            last_ping: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// `KEY=VALUE` lines in one datagram.
+    pub fn send(&self, state: &str) {
+        if let Err(e) = self.socket.send(state.as_bytes()) {
+            log::warning!("Couldn't notify the service manager: {e}");
+        }
+    }
+
+    /// Ping the watchdog when it is due.
+    pub fn ping(&self) {
+        let Some(interval) = self.watchdog else {
+            return;
+        };
+
+        let mut last_ping = self.last_ping.lock().unwrap_or_else(|e| e.into_inner());
+        if last_ping.elapsed() >= interval {
+            self.send("WATCHDOG=1");
+            *last_ping = Instant::now();
+        }
+    }
+}
+
+/// Take `NOTIFY_SOCKET` and the watchdog settings meant for this process out
+/// of the environment, so that greeters and sessions don't see them.
+pub fn init() {
+    let notifier = std::env::var(SOCKET_ENV).ok().and_then(|path| {
+        let watchdog = std::env::var(WATCHDOG_PID_ENV)
+            .map_or(true, |pid| pid == std::process::id().to_string())
+            .then(|| std::env::var(WATCHDOG_USEC_ENV).ok()?.parse().ok())
+            .flatten()
+            .map(Duration::from_micros);
+
+        Notifier::connect(&path, watchdog)
+            .inspect_err(|e| log::warning!("Couldn't connect to {path}: {e}"))
+            .ok()
+    });
+
+    // SAFETY: rilm is single threaded here.
+    unsafe {
+        std::env::remove_var(SOCKET_ENV);
+        std::env::remove_var(WATCHDOG_USEC_ENV);
+        std::env::remove_var(WATCHDOG_PID_ENV);
+    }
+
+    let _ = NOTIFIER.set(notifier);
+}
+
+fn send(state: &str) {
+    if let Some(Some(notifier)) = NOTIFIER.get() {
+        notifier.send(state);
+    }
+}
+
+pub fn ready() {
+    send("READY=1");
+}
+
+pub fn status(status: &str) {
+    send(&format!("STATUS={status}"));
+}
+
+pub fn stopping() {
+    send("STOPPING=1");
+}
+
+/// Called wherever rilm waits, see [`timeout`].
+pub fn ping() {
+    if let Some(Some(notifier)) = NOTIFIER.get() {
+        notifier.ping();
+    }
+}
+
+/// How long waiting may block before [`ping`] is due.
+pub fn timeout() -> PollTimeout {
+    match NOTIFIER.get() {
+        Some(Some(Notifier {
+            watchdog: Some(interval),
+            ..
+        })) => PollTimeout::try_from(*interval).unwrap_or(PollTimeout::MAX),
+        _ => PollTimeout::NONE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(socket: &UnixDatagram) -> String {
+        let mut buffer = [0; 256];
+        let len = socket.recv(&mut buffer).unwrap();
+        String::from_utf8_lossy(&buffer[..len]).into_owned()
+    }
+
+    #[test]
+    fn sends_states_and_pings_when_due() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let manager = UnixDatagram::bind(&path).unwrap();
+        manager.set_nonblocking(true).unwrap();
+
+        let notifier =
+            Notifier::connect(path.to_str().unwrap(), Some(Duration::from_millis(100))).unwrap();
+
+        notifier.send("READY=1\nSTATUS=greeter");
+        assert_eq!(receive(&manager), "READY=1\nSTATUS=greeter");
+
+        notifier.ping();
+        assert!(manager.recv(&mut [0; 16]).is_err());
+
+        std::thread::sleep(Duration::from_millis(60));
+        notifier.ping();
+        assert_eq!(receive(&manager), "WATCHDOG=1");
+
+        notifier.ping();
+        assert!(manager.recv(&mut [0; 16]).is_err());
+    }
+
+    #[test]
+    fn connects_to_abstract_sockets() {
+        let name = format!("rilm-test-{}", std::process::id());
+        let manager =
+            UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+
+        Notifier::connect(&format!("@{name}"), None)
+            .unwrap()
+            .send("STOPPING=1");
+
+        assert_eq!(receive(&manager), "STOPPING=1");
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    io::{ErrorKind, Read, StdinLock, Stdout, Write},
+    os::fd::AsFd,
+};
+
+use nix::sys::termios::{self, LocalFlags, SetArg, SpecialCharacterIndices, Termios};
+
+use crate::{
+    ipc::{Client, Response, wipe},
+    last, niri,
+    sessions::{self, Session},
+};
+
+use super::{Error, Result};
+
+const LEDS_PATH: &str = "/sys/class/leds";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Login,
+    Password,
+    /// A question PAM asks besides the password.
+    Answer,
+}
+
+enum Message {
+    Info(String),
+    Error(String),
+}
+
+struct Question {
+    text: String,
+    secret: bool,
+}
+
+struct Form {
+    login: String,
+    password: String,
+    focus: Focus,
+    sessions: Vec<Session>,
+    session: usize,
+    messages: Vec<Message>,
+    question: Option<Question>,
+    answer: String,
+}
+
+impl Form {
+    fn field(&mut self) -> &mut String {
+        match self.focus {
+            Focus::Login => &mut self.login,
+            Focus::Password => &mut self.password,
+            Focus::Answer => &mut self.answer,
+        }
+    }
+}
+
+enum Key {
+    Char(char),
+    Backspace,
+    Clear,
+    Enter,
+    Switch,
+    PreviousSession,
+    NextSession,
+    Other,
+}
+
+/// The login form shown in the greeter's terminal. Left and right pick the
+/// session to start.
+///
+/// PAM runs in the root process, which relays its questions and messages
+/// over the socket it passed through `RILM_SOCKET`.
+pub fn run() -> Result<()> {
+    let mut client = Client::from_env()?;
+
+    let stdin = std::io::stdin();
+    let _raw = RawMode::new(&stdin)?;
+
+    let login = std::env::var(last::USER_ENV).unwrap_or_default();
+    let sessions = sessions::discover();
+    let session = std::env::var(last::SESSION_ENV)
+        .ok()
+        .and_then(|id| sessions.iter().position(|session| session.id == id))
+        .unwrap_or_default();
+
+    let form = Form {
+        focus: match login.is_empty() {
+            true => Focus::Login,
+            false => Focus::Password,
+        },
+        login,
+        password: String::new(),
+        sessions,
+        session,
+        messages: std::env::var(niri::NOTICE_ENV)
+            .map(|notice| vec![Message::Error(notice)])
+            .unwrap_or_default(),
+        question: None,
+        answer: String::new(),
+    };
+
+    let mut terminal = Stdio {
+        input: stdin.lock(),
+        output: std::io::stdout(),
+    };
+
+    prompt(&mut client, &mut terminal, form)?;
+
+    // The root process stops the greeter once the session starts.
+    loop {
+        std::thread::park();
+    }
+}
+
+/// Show `form` on `terminal` until a session was started.
+fn prompt(client: &mut Client, terminal: &mut (impl Read + Write), mut form: Form) -> Result<()> {
+    loop {
+        render(terminal, &form)?;
+
+        match read_key(terminal)? {
+            Key::Char(c) => form.field().push(c),
+            Key::Backspace => drop(form.field().pop()),
+            Key::Clear => form.field().clear(),
+            Key::Switch => {
+                form.focus = match form.focus {
+                    Focus::Login => Focus::Password,
+                    Focus::Password | Focus::Answer => Focus::Login,
+                }
+            }
+            Key::PreviousSession => {
+                form.session = (form.session + form.sessions.len() - 1) % form.sessions.len()
+            }
+            Key::NextSession => form.session = (form.session + 1) % form.sessions.len(),
+            Key::Enter if form.focus == Focus::Login => form.focus = Focus::Password,
+            Key::Enter if form.login.trim().is_empty() => form.focus = Focus::Login,
+            Key::Enter => {
+                form.messages.clear();
+                form.messages
+                    .push(Message::Info(String::from("Authenticating...")));
+                render(terminal, &form)?;
+
+                let password = std::mem::take(&mut form.password);
+                let success = submit(client, terminal, &mut form, password)?;
+                render(terminal, &form)?;
+
+                if success {
+                    return Ok(());
+                }
+            }
+            Key::Other => {}
+        }
+    }
+}
+
+/// Log in with the form. The first secret question is answered with the
+/// password and one for the user name with the login, anything else PAM asks
+/// (a one-time code, a new password) is put to the user.
+fn submit(
+    client: &mut Client,
+    terminal: &mut (impl Read + Write),
+    form: &mut Form,
+    password: String,
+) -> Result<bool> {
+    let mut password = Some(password);
+    let mut response = client.create_session(form.login.trim())?;
+
+    form.messages.clear();
+
+    let success = loop {
+        response = match response {
+            Response::Prompt { secret: true, .. } if password.is_some() => {
+                client.answer(password.take())?
+            }
+            Response::Prompt {
+                secret: false,
+                text,
+            } if asks_for_login(&text) => client.answer(Some(form.login.trim().to_string()))?,
+            Response::Prompt { secret, text } => {
+                let answer = ask(terminal, form, Question { text, secret })?;
+                client.answer(Some(answer))?
+            }
+            Response::Info(msg) => {
+                form.messages.push(Message::Info(msg));
+                client.receive()?
+            }
+            Response::Error(msg) => {
+                form.messages.push(Message::Error(msg));
+                client.receive()?
+            }
+            Response::Success => break true,
+            Response::Failure(reason) => {
+                form.messages
+                    .push(Message::Error(format!("Login incorrect: {reason}")));
+                break false;
+            }
+        }
+    };
+
+    if let Some(password) = password {
+        wipe(password);
+    }
+
+    if !success {
+        form.focus = Focus::Password;
+        return Ok(false);
+    }
+
+    let session = &form.sessions[form.session];
+    match client.start_session(session.exec.clone(), session.env())? {
+        Response::Success => {
+            form.messages
+                .push(Message::Info(String::from("Starting session...")));
+            Ok(true)
+        }
+        Response::Failure(reason) | Response::Error(reason) => {
+            form.messages.push(Message::Error(reason));
+            Ok(false)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Put `question` under the form and read the reply, until Enter.
+fn ask(terminal: &mut (impl Read + Write), form: &mut Form, question: Question) -> Result<String> {
+    let focus = std::mem::replace(&mut form.focus, Focus::Answer);
+    form.question = Some(question);
+
+    loop {
+        render(terminal, form)?;
+
+        match read_key(terminal)? {
+            Key::Char(c) => form.answer.push(c),
+            Key::Backspace => drop(form.answer.pop()),
+            Key::Clear => form.answer.clear(),
+            Key::Enter => break,
+            _ => {}
+        }
+    }
+
+    form.question = None;
+    form.focus = focus;
+
+    Ok(std::mem::take(&mut form.answer))
+}
+
+/// Whether a visible PAM question asks for the user name, which the form
+/// already has.
+fn asks_for_login(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text.contains("login") || text.contains("user")
+}
+
+fn render(out: &mut impl Write, form: &Form) -> Result<()> {
+    let host = nix::sys::utsname::uname()
+        .map(|uts| uts.nodename().to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut screen = format!("\x1b[H\x1b[2J\r\n  {host}\r\n\r\n");
+
+    screen.push_str(&format!("  login:    {}\r\n", form.login));
+    screen.push_str(&format!(
+        "  password: {}\r\n",
+        "*".repeat(form.password.chars().count())
+    ));
+    screen.push_str(&format!(
+        "  session:  < {} >\r\n\r\n",
+        form.sessions[form.session].name
+    ));
+
+    let caps_lock = caps_lock();
+    if caps_lock {
+        screen.push_str("  Caps Lock is on\r\n\r\n");
+    }
+
+    for message in &form.messages {
+        match message {
+            Message::Info(msg) => screen.push_str(&format!("  {msg}\r\n")),
+            Message::Error(msg) => screen.push_str(&format!("  \x1b[31m{msg}\x1b[0m\r\n")),
+        }
+    }
+
+    let mut question_col = 0;
+    if let Some(question) = &form.question {
+        let answer = match question.secret {
+            true => "*".repeat(form.answer.chars().count()),
+            false => form.answer.clone(),
+        };
+        let text = format!("  {} ", question.text.trim_end());
+        question_col = text.chars().count() + 1;
+        screen.push_str(&format!("{text}{answer}\r\n"));
+    }
+
+    let (row, col) = match form.focus {
+        Focus::Login => (4, 13 + form.login.chars().count()),
+        Focus::Password => (5, 13 + form.password.chars().count()),
+        Focus::Answer => (
+            8 + 2 * usize::from(caps_lock) + form.messages.len(),
+            question_col + form.answer.chars().count(),
+        ),
+    };
+    screen.push_str(&format!("\x1b[{row};{col}H"));
+
+    out.write_all(screen.as_bytes())?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn read_key(input: &mut impl Read) -> Result<Key> {
+    let mut byte = [0u8; 1];
+    let mut read = |input: &mut dyn Read| -> Result<u8> {
+        loop {
+            match input.read(&mut byte) {
+                Ok(0) => return Err(Error::IoError(ErrorKind::UnexpectedEof.into())),
+                Ok(_) => return Ok(byte[0]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    };
+
+    let first = read(input)?;
+    let key = match first {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Switch,
+        0x7f | 0x08 => Key::Backspace,
+        0x15 | 0x03 => Key::Clear,
+        0x1b => match (read(input)?, read(input)?) {
+            (b'[', b'A' | b'B') => Key::Switch,
+            (b'[', b'D') => Key::PreviousSession,
+            (b'[', b'C') => Key::NextSession,
+            _ => Key::Other,
+        },
+        byte if byte < 0x20 => Key::Other,
+        byte => {
+            let len = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+
+            let mut buf = vec![byte];
+            for _ in 1..len {
+                buf.push(read(input)?);
+            }
+
+            match String::from_utf8(buf).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Other,
+            }
+        }
+    };
+
+    Ok(key)
+}
+
+/// Whether any keyboard reports its Caps Lock LED as lit.
+fn caps_lock() -> bool {
+    let Ok(leds) = std::fs::read_dir(LEDS_PATH) else {
+        return false;
+    };
+
+    leds.flatten()
+        .filter(|led| led.file_name().to_string_lossy().ends_with("::capslock"))
+        .filter_map(|led| std::fs::read_to_string(led.path().join("brightness")).ok())
+        .any(|brightness| brightness.trim() != "0")
+}
+
+/// stdin and stdout as one terminal.
+struct Stdio<'a> {
+    input: StdinLock<'a>,
+    output: Stdout,
+}
+
+impl Read for Stdio<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Stdio<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Puts the terminal in non-canonical mode without echo until dropped.
+struct RawMode<'fd, F: AsFd> {
+    fd: &'fd F,
+    saved: Termios,
+}
+
+impl<'fd, F: AsFd> RawMode<'fd, F> {
+    fn new(fd: &'fd F) -> Result<Self> {
+        let saved = termios::tcgetattr(fd)?;
+
+        let mut raw = saved.clone();
+        raw.local_flags
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::ISIG);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        termios::tcsetattr(fd, SetArg::TCSAFLUSH, &raw)?;
+
+        Ok(Self { fd, saved })
+    }
+}
+
+impl<F: AsFd> Drop for RawMode<'_, F> {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(self.fd, SetArg::TCSANOW, &self.saved);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixStream, thread};
+
+    use authkit::tty::Pty;
+
+    use super::*;
+    use crate::ipc::{self, Request};
+
+    fn question(secret: bool, text: &str) -> Response {
+        Response::Prompt {
+            secret,
+            text: text.to_string(),
+        }
+    }
+
+    fn answer(answer: &str) -> Request {
+        Request::AnswerPrompt {
+            answer: Some(answer.to_string()),
+        }
+    }
+
+    /// Type `input` into the prompt on a pseudo-terminal while the root side
+    /// replies with `responses`, one per request. Returns the requests and
+    /// what was shown.
+    fn log_in<const N: usize>(responses: [Response; N], input: &[u8]) -> (Vec<Request>, String) {
+        let (client, server) = UnixStream::pair().unwrap();
+
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for response in responses {
+                requests.push(ipc::receive::<Request>(&server).unwrap().unwrap());
+                ipc::send(&server, &response).unwrap();
+            }
+
+            requests
+        });
+
+        let mut pty = Pty::open(1, 1).unwrap();
+        let slave = pty.as_fd().try_clone_to_owned().unwrap();
+        let raw = RawMode::new(&slave).unwrap();
+
+        let mut master = pty.master().try_clone().unwrap();
+        let screen = thread::spawn(move || {
+            let mut screen = Vec::new();
+            let _ = master.read_to_end(&mut screen);
+            String::from_utf8_lossy(&screen).into_owned()
+        });
+
+        pty.master().write_all(input).unwrap();
+
+        let form = Form {
+            login: String::new(),
+            password: String::new(),
+            focus: Focus::Login,
+            sessions: vec![Session::builtin()],
+            session: 0,
+            messages: Vec::new(),
+            question: None,
+            answer: String::new(),
+        };
+        prompt(&mut Client::new(client), &mut pty, form).unwrap();
+
+        drop(raw);
+        drop(slave);
+        drop(pty);
+
+        (server.join().unwrap(), screen.join().unwrap())
+    }
+
+    #[test]
+    fn logs_in_through_a_pseudo_terminal() {
+        let (requests, screen) = log_in(
+            [
+                question(true, "Password: "),
+                Response::Failure(String::from("bad password")),
+                question(true, "Password: "),
+                Response::Success,
+                Response::Success,
+            ],
+            b"erin\rwrong\rhunter2\r",
+        );
+
+        let create = Request::CreateSession {
+            user: String::from("erin"),
+        };
+        assert_eq!(
+            requests,
+            [
+                create.clone(),
+                answer("wrong"),
+                create,
+                answer("hunter2"),
+                Request::StartSession {
+                    command: Vec::new(),
+                    env: Session::builtin().env(),
+                },
+            ]
+        );
+
+        assert!(screen.contains("Login incorrect: bad password"));
+        assert!(screen.contains("Starting session..."));
+        assert!(!screen.contains("hunter2"));
+    }
+
+    #[test]
+    fn other_questions_are_put_to_the_user() {
+        let (requests, screen) = log_in(
+            [
+                question(false, "login: "),
+                question(false, "Verification code: "),
+                question(true, "Password: "),
+                question(true, "New password: "),
+                Response::Success,
+                Response::Success,
+            ],
+            b"erin\rhunter2\r123456\rs3cret\r",
+        );
+
+        assert_eq!(
+            requests,
+            [
+                Request::CreateSession {
+                    user: String::from("erin"),
+                },
+                answer("erin"),
+                answer("123456"),
+                answer("hunter2"),
+                answer("s3cret"),
+                Request::StartSession {
+                    command: Vec::new(),
+                    env: Session::builtin().env(),
+                },
+            ]
+        );
+
+        assert!(screen.contains("Verification code: 123456"));
+        assert!(screen.contains("New password: ******"));
+        assert!(!screen.contains("s3cret"));
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    fmt,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    sys::signal::{Signal, kill},
+    unistd::Pid,
+};
+
+use crate::log;
+
+use super::Result;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long killed processes get to disappear before the cgroup is removed.
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// Everything a child started, so that it can be stopped as a whole.
+#[derive(Debug)]
+pub enum Scope {
+    /// The process group and session led by this pid.
+    Group(Pid),
+    Cgroup(PathBuf),
+}
+
+/// A process that was still running when its scope was stopped.
+#[derive(Debug, PartialEq)]
+pub struct Leftover {
+    pub pid: Pid,
+    pub name: String,
+}
+
+impl fmt::Display for Leftover {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.pid, self.name)
+    }
+}
+
+impl Scope {
+    /// A cgroup named after `leader` under the one rilm runs in, `None` when
+    /// the cgroup tree isn't writable.
+    pub fn cgroup(leader: Pid) -> Option<Self> {
+        let own = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+        let own = own.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+        let own = Path::new(CGROUP_ROOT).join(own.trim_start_matches('/'));
+        // Only the unified hierarchy, not the legacy one's tmpfs.
+        if !own.join("cgroup.procs").exists() {
+            return None;
+        }
+
+        let path = own.join(format!("rilm-{leader}"));
+
+        if let Err(e) = std::fs::create_dir(&path) {
+            if e.kind() != ErrorKind::PermissionDenied && e.kind() != ErrorKind::ReadOnlyFilesystem
+            {
+                log::warning!("Couldn't create cgroup {}: {e}", path.display());
+            }
+            return None;
+        }
+
+        if let Err(e) = std::fs::write(path.join("cgroup.procs"), leader.to_string()) {
+            log::warning!("Couldn't move {leader} to cgroup {}: {e}", path.display());
+            let _ = std::fs::remove_dir(&path);
+            return None;
+        }
+
+        Some(Self::Cgroup(path))
+    }
+
+    /// Processes still running in the scope.
+    pub fn members(&self) -> Vec<Leftover> {
+        match self {
+            Self::Group(leader) => processes()
+                .filter(|process| process.group == *leader || process.session == *leader)
+                .map(|process| Leftover {
+                    pid: process.pid,
+                    name: process.name,
+                })
+                .collect(),
+            Self::Cgroup(path) => std::fs::read_to_string(path.join("cgroup.procs"))
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|pid| pid.parse().ok())
+                .map(|pid| Leftover {
+                    pid: Pid::from_raw(pid),
+                    name: std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                        .map(|name| name.trim_end().to_string())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// SIGTERM everything in the scope, then SIGKILL what is still there
+    /// after `timeout`. Returns what had to be killed.
+    pub fn stop(&self, timeout: Duration) -> Result<Vec<Leftover>> {
+        self.signal(Signal::SIGTERM)?;
+
+        let deadline = Instant::now() + timeout;
+        while !self.members().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        let killed = self.members();
+        if !killed.is_empty() {
+            self.signal(Signal::SIGKILL)?;
+
+            let deadline = Instant::now() + KILL_GRACE;
+            while !self.members().is_empty() && Instant::now() < deadline {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        if let Self::Cgroup(path) = self
+            && let Err(e) = std::fs::remove_dir(path)
+        {
+            log::warning!("Couldn't remove cgroup {}: {e}", path.display());
+        }
+
+        Ok(killed)
+    }
+
+    fn signal(&self, signal: Signal) -> Result<()> {
+        for member in self.members() {
+            match kill(member.pid, signal) {
+                Err(Errno::ESRCH) => {}
+                result => result?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Process {
+    pid: Pid,
+    name: String,
+    group: Pid,
+    session: Pid,
+}
+
+/// Every live process, from `/proc/<pid>/stat`.
+fn processes() -> impl Iterator<Item = Process> {
+    std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            parse_stat(
+                pid,
+                &std::fs::read_to_string(entry.path().join("stat")).ok()?,
+            )
+        })
+}
+
+/// `pid (name) state ppid pgrp session ...`, where the name may contain
+/// anything including parentheses. Zombies are left out.
+fn parse_stat(pid: i32, stat: &str) -> Option<Process> {
+    let (start, rest) = stat.split_once(" (")?;
+    let (name, rest) = rest.rsplit_once(") ")?;
+    let mut fields = rest.split_whitespace();
+
+    let state = fields.next()?;
+    let _ppid = fields.next()?;
+    let group = fields.next()?.parse().ok()?;
+    let session = fields.next()?.parse().ok()?;
+
+    (start.parse() == Ok(pid) && state != "Z").then(|| Process {
+        pid: Pid::from_raw(pid),
+        name: name.to_string(),
+        group: Pid::from_raw(group),
+        session: Pid::from_raw(session),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_with_parentheses() {
+        let process = parse_stat(42, "42 (a) b (c)) S 1 40 41 0 -1 4194560").unwrap();
+
+        assert_eq!(process.name, "a) b (c)");
+        assert_eq!(process.group, Pid::from_raw(40));
+        assert_eq!(process.session, Pid::from_raw(41));
+
+        assert!(parse_stat(42, "42 (zombie) Z 1 40 41 0").is_none());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+pub const WAYLAND_SESSIONS: &str = "/usr/share/wayland-sessions";
+pub const X_SESSIONS: &str = "/usr/share/xsessions";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Wayland,
+    X11,
+}
+
+impl Kind {
+    pub fn session_type(self) -> &'static str {
+        match self {
+            Self::Wayland => "wayland",
+            Self::X11 => "x11",
+        }
+    }
+}
+
+/// A session the user can pick in the greeter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    /// Desktop file name without `.desktop`, used as `XDG_SESSION_DESKTOP`.
+    pub id: String,
+    pub name: String,
+    /// Empty for the built-in niri session.
+    pub exec: Vec<String>,
+    pub desktop_names: Vec<String>,
+    pub kind: Kind,
+}
+
+impl Session {
+    /// niri with rilm's own configuration.
+    pub fn builtin() -> Self {
+        Self {
+            id: String::from("rilm"),
+            name: String::from("Niri (rilm)"),
+            exec: Vec::new(),
+            desktop_names: vec![String::from("niri")],
+            kind: Kind::Wayland,
+        }
+    }
+
+    /// The variables the session is started with, as `KEY=VALUE`.
+    pub fn env(&self) -> Vec<String> {
+        vec![
+            format!("XDG_SESSION_TYPE={}", self.kind.session_type()),
+            format!("XDG_SESSION_DESKTOP={}", self.id),
+            format!("XDG_CURRENT_DESKTOP={}", self.desktop_names.join(":")),
+        ]
+    }
+}
+
+/// The built-in session followed by every installed Wayland then X11
+/// session, sorted by name.
+pub fn discover() -> Vec<Session> {
+    let mut sessions = vec![Session::builtin()];
+
+    for (dir, kind) in [(WAYLAND_SESSIONS, Kind::Wayland), (X_SESSIONS, Kind::X11)] {
+        let mut found = scan(Path::new(dir), kind);
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        sessions.extend(found);
+    }
+
+    sessions
+}
+
+pub fn scan(dir: &Path, kind: Kind) -> Vec<Session> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "desktop"))
+        .filter_map(|path| {
+            let id = path.file_stem()?.to_string_lossy().into_owned();
+            parse(&id, &std::fs::read_to_string(&path).ok()?, kind)
+        })
+        .collect()
+}
+
+/// Read a session from the `[Desktop Entry]` group of a desktop file, `None`
+/// if it is hidden, broken or its `TryExec` isn't installed.
+pub fn parse(id: &str, contents: &str, kind: Kind) -> Option<Session> {
+    let mut in_entry = false;
+    let (mut name, mut exec, mut try_exec, mut desktop_names) = (None, None, None, None);
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=').filter(|_| in_entry) else {
+            continue;
+        };
+
+        let value = unescape(value.trim());
+        match key.trim() {
+            "Name" => name = Some(value),
+            "Exec" => exec = Some(value),
+            "TryExec" => try_exec = Some(value),
+            "DesktopNames" => desktop_names = Some(value),
+            "Hidden" if value == "true" => return None,
+            _ => {}
+        }
+    }
+
+    if try_exec.is_some_and(|program| find_program(&program).is_none()) {
+        return None;
+    }
+
+    let exec = split_exec(&exec?)?;
+    if exec.is_empty() {
+        return None;
+    }
+
+    Some(Session {
+        id: id.to_string(),
+        name: name.unwrap_or_else(|| id.to_string()),
+        exec,
+        desktop_names: desktop_names
+            .map(|names| {
+                names
+                    .split(';')
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_else(|| vec![id.to_string()]),
+        kind,
+    })
+}
+
+/// The escapes allowed in any string value.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('s') => out.push(' '),
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('r') => out.push('\r'),
+                Some('\\') => out.push('\\'),
+                Some(other) => {
+                    out.push('\\');
+                    out.push(other);
+                }
+                None => out.push('\\'),
+            },
+            (c, false) => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Split an `Exec` value into arguments, following the quoting rules of the
+/// desktop entry spec and dropping field codes. `None` if quotes don't match.
+fn split_exec(exec: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut arg = None::<String>;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => args.extend(arg.take()),
+            '"' => {
+                let arg = arg.get_or_insert_default();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => arg.push(chars.next()?),
+                        c => arg.push(c),
+                    }
+                }
+            }
+            '%' => {
+                if chars.next()? == '%' {
+                    arg.get_or_insert_default().push('%');
+                }
+            }
+            c => arg.get_or_insert_default().push(c),
+        }
+    }
+
+    args.extend(arg);
+    Some(args)
+}
+
+/// Resolve `program` like execvp would.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let executable = |path: &Path| {
+        std::fs::metadata(path)
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return executable(&path).then_some(path);
+    }
+
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dir| dir.join(program))
+        .find(|path| executable(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_wayland_session() {
+        let session = parse(
+            "sway",
+            "[Desktop Entry]\n\
+             Name=Sway\n\
+             Comment=An i3-compatible Wayland compositor\n\
+             Exec=sway --unsupported-gpu \"-c\" \"/etc/sway/my config\" %U\n\
+             TryExec=sh\n\
+             DesktopNames=sway;wlroots;\n\
+             \n\
+             [Desktop Action Debug]\n\
+             Exec=sway -d\n",
+            Kind::Wayland,
+        )
+        .unwrap();
+
+        assert_eq!(session.name, "Sway");
+        assert_eq!(
+            session.exec,
+            ["sway", "--unsupported-gpu", "-c", "/etc/sway/my config"]
+        );
+        assert_eq!(
+            session.env(),
+            [
+                "XDG_SESSION_TYPE=wayland",
+                "XDG_SESSION_DESKTOP=sway",
+                "XDG_CURRENT_DESKTOP=sway:wlroots"
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_hidden_and_missing_sessions() {
+        let hidden = "[Desktop Entry]\nName=Old\nExec=old\nHidden=true\n";
+        let missing = "[Desktop Entry]\nName=Gone\nExec=gone\nTryExec=/nonexistent/gone\n";
+        let broken = "[Desktop Entry]\nName=Broken\nExec=\"unterminated\n";
+
+        assert_eq!(parse("old", hidden, Kind::Wayland), None);
+        assert_eq!(parse("gone", missing, Kind::Wayland), None);
+        assert_eq!(parse("broken", broken, Kind::X11), None);
+    }
+
+    #[test]
+    fn defaults_desktop_names_to_id() {
+        let session = parse("gnome", "[Desktop Entry]\nExec=gnome-session\n", Kind::X11).unwrap();
+
+        assert_eq!(session.name, "gnome");
+        assert_eq!(session.desktop_names, ["gnome"]);
+        assert_eq!(session.env()[0], "XDG_SESSION_TYPE=x11");
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    cell::Cell,
+    os::fd::{AsFd, BorrowedFd},
+};
+
+use nix::{
+    errno::Errno,
+    poll::{PollFd, PollFlags},
+    sys::{
+        signal::{SigSet, SigmaskHow, Signal},
+        signalfd::{SfdFlags, SignalFd},
+        wait::WaitStatus,
+    },
+};
+
+use crate::{log, notify, spawn::Child};
+
+use super::Result;
+
+/// SIGTERM, SIGINT and SIGHUP sent to the daemon, read from a signalfd so
+/// that they are handled between steps instead of in a handler.
+///
+/// Children get them unblocked again when they are spawned.
+pub struct Signals {
+    fd: SignalFd,
+    stop: Cell<Option<Signal>>,
+    reload: Cell<bool>,
+}
+
+impl Signals {
+    pub fn install() -> Result<Self> {
+        let mut mask = SigSet::empty();
+        mask.add(Signal::SIGTERM);
+        mask.add(Signal::SIGINT);
+        mask.add(Signal::SIGHUP);
+
+        nix::sys::signal::sigprocmask(SigmaskHow::SIG_BLOCK, Some(&mask), None)?;
+
+        Ok(Self {
+            fd: SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)?,
+            stop: Cell::new(None),
+            reload: Cell::new(false),
+        })
+    }
+
+    /// Take in what arrived. Returns the last signal asking to stop, for the
+    /// caller to forward to the greeter or session it runs.
+    pub fn read(&self) -> Result<Option<Signal>> {
+        let mut forward = None;
+
+        while let Some(info) = self.fd.read_signal()? {
+            match Signal::try_from(info.ssi_signo as i32)? {
+                Signal::SIGHUP => self.reload.set(true),
+                signal => {
+                    self.stop.set(Some(signal));
+                    forward = Some(signal);
+                }
+            }
+        }
+
+        Ok(forward)
+    }
+
+    /// The signal that asked the daemon to stop, if one did.
+    pub fn stop(&self) -> Option<Signal> {
+        self.stop.get()
+    }
+
+    /// How the daemon ends after `result`: once a signal asked it to stop,
+    /// stopping is what it was told to do, and what failed on the way out is
+    /// only logged.
+    pub fn finish(&self, result: Result<()>) -> Result<()> {
+        let Some(signal) = self.stop.get() else {
+            return result;
+        };
+
+        if let Err(e) = result {
+            log::error!("Failed while stopping: {e}");
+        }
+        log::info!("Stopped by {signal}");
+
+        Ok(())
+    }
+
+    /// Whether a signal asks for something the daemon hasn't done yet.
+    pub fn pending(&self) -> bool {
+        self.stop.get().is_some() || self.reload.get()
+    }
+
+    /// Whether SIGHUP asked for the configuration to be reloaded since the
+    /// last call.
+    pub fn take_reload(&self) -> bool {
+        self.reload.replace(false)
+    }
+
+    /// Wait for `child` to exit, passing on the signals that ask to stop.
+    pub fn wait(&self, child: &Child) -> Result<WaitStatus> {
+        loop {
+            let mut fds = [
+                PollFd::new(child.as_fd(), PollFlags::POLLIN),
+                PollFd::new(self.fd.as_fd(), PollFlags::POLLIN),
+            ];
+
+            match nix::poll::poll(&mut fds, notify::timeout()) {
+                Err(Errno::EINTR) => continue,
+                ready => ready?,
+            };
+            notify::ping();
+
+            if let Some(signal) = self.read()? {
+                child.signal(signal)?;
+            }
+
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+        }
+    }
+}
+
+impl AsFd for Signals {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_and_reload_are_kept_apart() {
+        // Signals are blocked and raised for this thread only.
+        let signals = Signals::install().unwrap();
+        assert!(!signals.pending());
+
+        nix::sys::signal::raise(Signal::SIGHUP).unwrap();
+        assert_eq!(signals.read().unwrap(), None);
+        assert!(signals.pending());
+        assert!(signals.take_reload());
+        assert!(!signals.take_reload());
+
+        nix::sys::signal::raise(Signal::SIGTERM).unwrap();
+        assert_eq!(signals.read().unwrap(), Some(Signal::SIGTERM));
+        assert_eq!(signals.stop(), Some(Signal::SIGTERM));
+        assert!(signals.pending());
+    }
+
+    #[test]
+    fn failures_after_a_stop_signal_are_a_clean_exit() {
+        let signals = Signals::install().unwrap();
+        let failed = || Err(Errno::EIO.into());
+        assert!(signals.finish(failed()).is_err());
+
+        nix::sys::signal::raise(Signal::SIGINT).unwrap();
+        signals.read().unwrap();
+        assert!(signals.finish(failed()).is_ok());
+        assert!(signals.finish(Ok(())).is_ok());
+    }
+}
@@ -0,0 +1,539 @@
+use std::{
+    cell::Cell,
+    ffi::{CString, OsString, c_char},
+    fmt,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::PathBuf,
+    time::Duration,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    poll::{PollFd, PollFlags, PollTimeout},
+    sys::{
+        resource::{Resource, rlim_t},
+        signal::{SigSet, SigmaskHow, Signal},
+        stat::Mode,
+        wait::{WaitPidFlag, WaitStatus},
+    },
+    unistd::{ForkResult, Gid, Pid, Uid},
+};
+
+use crate::scope::{Leftover, Scope};
+
+use super::{Error, Result};
+
+/// Everything a child process is set up with before it execs.
+pub struct Spec {
+    program: OsString,
+    args: Vec<OsString>,
+    env: Option<Vec<CString>>,
+    user: Option<(Uid, Gid, Vec<Gid>)>,
+    cwd: Option<PathBuf>,
+    tty: Option<PathBuf>,
+    rlimits: Vec<(Resource, rlim_t, rlim_t)>,
+    isolate: bool,
+}
+
+/// What the child was doing when it failed, reported to the parent.
+#[derive(Clone, Copy, Debug)]
+enum Step {
+    Signals,
+    Tty,
+    Group,
+    Rlimit,
+    Groups,
+    Gid,
+    Uid,
+    Cwd,
+    Exec,
+}
+
+impl Step {
+    const ALL: [Step; 9] = [
+        Step::Signals,
+        Step::Tty,
+        Step::Group,
+        Step::Rlimit,
+        Step::Groups,
+        Step::Gid,
+        Step::Uid,
+        Step::Cwd,
+        Step::Exec,
+    ];
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Signals => "unblocking signals",
+            Self::Tty => "taking the terminal",
+            Self::Group => "creating the process group",
+            Self::Rlimit => "setting resource limits",
+            Self::Groups => "setting groups",
+            Self::Gid => "setting the group",
+            Self::Uid => "setting the user",
+            Self::Cwd => "changing directory",
+            Self::Exec => "exec",
+        })
+    }
+}
+
+/// The spec turned into what the child needs, so that nothing is allocated
+/// between fork and exec: the null-terminated `argv` and `envp` arrays
+/// execve takes are built here too.
+struct Prepared {
+    program: CString,
+    /// What `argv` points into.
+    _args: Vec<CString>,
+    argv: Vec<*const c_char>,
+    /// Points into the spec's environment.
+    envp: Option<Vec<*const c_char>>,
+    cwd: Option<CString>,
+    tty: Option<OwnedFd>,
+}
+
+impl Spec {
+    /// Run `program` with itself as `argv[0]`, looked up in `PATH` unless it
+    /// contains a `/`.
+    pub fn new(program: impl Into<OsString>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            env: None,
+            user: None,
+            cwd: None,
+            tty: None,
+            rlimits: Vec::new(),
+            isolate: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<A: Into<OsString>>(mut self, args: impl IntoIterator<Item = A>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Replace the environment with these `KEY=VALUE` variables, the current
+    /// one is inherited otherwise.
+    pub fn env(mut self, env: Vec<CString>) -> Self {
+        self.env = Some(env);
+        self
+    }
+
+    /// Drop to `uid` and `gid`, with `groups` as supplementary groups.
+    pub fn user(mut self, uid: Uid, gid: Gid, groups: Vec<Gid>) -> Self {
+        self.user = Some((uid, gid, groups));
+        self
+    }
+
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Start a new session with `tty` as controlling terminal and standard
+    /// input. Output stays where rilm's goes, so that it ends up in its logs.
+    pub fn tty(mut self, tty: impl Into<PathBuf>) -> Self {
+        self.tty = Some(tty.into());
+        self
+    }
+
+    pub fn rlimit(mut self, resource: Resource, soft: rlim_t, hard: rlim_t) -> Self {
+        self.rlimits.push((resource, soft, hard));
+        self
+    }
+
+    /// Give the child its own process group, and its own cgroup when rilm
+    /// may create one, so that it can be stopped with everything it started.
+    pub fn isolate(mut self) -> Self {
+        self.isolate = true;
+        self
+    }
+
+    /// Fork and exec. Errors up to and including exec are returned here
+    /// rather than lost in the child.
+    pub fn spawn(&self) -> Result<Child> {
+        let prepared = self.prepare()?;
+        let (read, write) = nix::unistd::pipe2(OFlag::O_CLOEXEC)?;
+        // Holds the child back until it is in its cgroup.
+        let (go_read, go_write) = nix::unistd::pipe2(OFlag::O_CLOEXEC)?;
+
+        match unsafe { nix::unistd::fork() }? {
+            ForkResult::Child => {
+                drop(read);
+                drop(go_write);
+
+                let mut byte = [0u8; 1];
+                while nix::unistd::read(&go_read, &mut byte) == Err(Errno::EINTR) {}
+
+                let (step, errno) = self.setup(&prepared);
+                let mut report = [0u8; 5];
+                report[0] = step as u8;
+                report[1..].copy_from_slice(&(errno as i32).to_ne_bytes());
+                let _ = nix::unistd::write(&write, &report);
+
+                unsafe { nix::libc::_exit(127) }
+            }
+            ForkResult::Parent { child } => {
+                drop(write);
+                drop(go_read);
+
+                let scope = self
+                    .isolate
+                    .then(|| Scope::cgroup(child).unwrap_or(Scope::Group(child)));
+                drop(go_write);
+
+                let mut report = [0u8; 5];
+                let mut len = 0;
+                while len < report.len() {
+                    match nix::unistd::read(&read, &mut report[len..]) {
+                        Ok(0) => break,
+                        Ok(n) => len += n,
+                        Err(Errno::EINTR) => continue,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+
+                if len == 0 {
+                    return Ok(Child {
+                        pid: child,
+                        pidfd: pidfd_open(child)?,
+                        scope,
+                        status: Cell::new(None),
+                    });
+                }
+
+                while let Err(Errno::EINTR) = nix::sys::wait::waitpid(child, None) {}
+                if let Some(scope) = scope {
+                    let _ = scope.stop(Duration::ZERO);
+                }
+
+                let step = Step::ALL[usize::from(report[0]).min(Step::ALL.len() - 1)];
+                let errno = Errno::from_raw(i32::from_ne_bytes(
+                    report[1..].try_into().unwrap_or_default(),
+                ));
+                Err(self.error(step, errno))
+            }
+        }
+    }
+
+    /// Set up the current process and exec in place, only returns on failure.
+    pub fn exec(&self) -> Error {
+        match self.prepare() {
+            Ok(prepared) => {
+                let (step, errno) = self.setup(&prepared);
+                self.error(step, errno)
+            }
+            Err(e) => e,
+        }
+    }
+
+    fn prepare(&self) -> Result<Prepared> {
+        let program = CString::new(self.program.as_bytes())?;
+
+        let args = std::iter::once(&self.program)
+            .chain(self.args.iter())
+            .map(|arg| CString::new(arg.as_bytes()))
+            .collect::<core::result::Result<Vec<_>, _>>()?;
+        let argv = pointers(&args);
+        let envp = self.env.as_deref().map(pointers);
+
+        let cwd = self
+            .cwd
+            .as_ref()
+            .map(|dir| CString::new(dir.as_os_str().as_bytes()))
+            .transpose()?;
+
+        let tty = self
+            .tty
+            .as_ref()
+            .map(|tty| {
+                nix::fcntl::open(
+                    tty,
+                    OFlag::O_RDWR | OFlag::O_NOCTTY | OFlag::O_CLOEXEC,
+                    Mode::empty(),
+                )
+            })
+            .transpose()?;
+
+        Ok(Prepared {
+            program,
+            _args: args,
+            argv,
+            envp,
+            cwd,
+            tty,
+        })
+    }
+
+    /// Runs between fork and exec: only system calls on what [`Spec::prepare`]
+    /// built, nothing is allocated.
+    fn setup(&self, prepared: &Prepared) -> (Step, Errno) {
+        let result = (|| {
+            // rilm blocks the signals it reads from a signalfd.
+            nix::sys::signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&SigSet::empty()), None)
+                .map_err(|e| (Step::Signals, e))?;
+
+            if let Some(tty) = &prepared.tty {
+                let attach = || {
+                    nix::unistd::setsid()?;
+                    Errno::result(unsafe {
+                        nix::libc::ioctl(tty.as_raw_fd(), nix::libc::TIOCSCTTY, 1)
+                    })?;
+                    nix::unistd::dup2_stdin(tty)
+                };
+                attach().map_err(|e| (Step::Tty, e))?;
+            } else if self.isolate {
+                nix::unistd::setpgid(Pid::from_raw(0), Pid::from_raw(0))
+                    .map_err(|e| (Step::Group, e))?;
+            }
+
+            for &(resource, soft, hard) in &self.rlimits {
+                nix::sys::resource::setrlimit(resource, soft, hard)
+                    .map_err(|e| (Step::Rlimit, e))?;
+            }
+
+            if let Some((uid, gid, groups)) = &self.user {
+                nix::unistd::setgroups(groups).map_err(|e| (Step::Groups, e))?;
+                nix::unistd::setgid(*gid).map_err(|e| (Step::Gid, e))?;
+                nix::unistd::setuid(*uid).map_err(|e| (Step::Uid, e))?;
+            }
+
+            if let Some(cwd) = &prepared.cwd {
+                nix::unistd::chdir(cwd.as_c_str()).map_err(|e| (Step::Cwd, e))?;
+            }
+
+            // nix's exec functions allocate the pointer arrays themselves.
+            unsafe {
+                match &prepared.envp {
+                    Some(envp) => nix::libc::execvpe(
+                        prepared.program.as_ptr(),
+                        prepared.argv.as_ptr(),
+                        envp.as_ptr(),
+                    ),
+                    None => nix::libc::execvp(prepared.program.as_ptr(), prepared.argv.as_ptr()),
+                };
+            }
+            Err((Step::Exec, Errno::last()))
+        })();
+
+        match result {
+            Ok(()) => (Step::Exec, Errno::UnknownErrno),
+            Err(failure) => failure,
+        }
+    }
+
+    fn error(&self, step: Step, errno: Errno) -> Error {
+        Error::Spawn(format!(
+            "{}: {step} failed: {errno}",
+            self.program.display()
+        ))
+    }
+}
+
+/// A null-terminated array of pointers to `strings`, valid as long as they
+/// are.
+fn pointers(strings: &[CString]) -> Vec<*const c_char> {
+    strings
+        .iter()
+        .map(|string| string.as_ptr())
+        .chain([std::ptr::null()])
+        .collect()
+}
+
+/// A started process, reaped by [`Child::wait`]. Its pidfd becomes readable
+/// when it exits.
+#[derive(Debug)]
+pub struct Child {
+    pid: Pid,
+    pidfd: OwnedFd,
+    scope: Option<Scope>,
+    /// Kept once reaped, waiting again returns it.
+    status: Cell<Option<WaitStatus>>,
+}
+
+impl Child {
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn wait(&self) -> Result<WaitStatus> {
+        if let Some(status) = self.status.get() {
+            return Ok(status);
+        }
+
+        loop {
+            match nix::sys::wait::waitpid(self.pid, None) {
+                Err(Errno::EINTR) => continue,
+                result => return Ok(self.reaped(result?)),
+            }
+        }
+    }
+
+    /// `None` while it is still running.
+    pub fn try_wait(&self) -> Result<Option<WaitStatus>> {
+        if let Some(status) = self.status.get() {
+            return Ok(Some(status));
+        }
+
+        match nix::sys::wait::waitpid(self.pid, Some(WaitPidFlag::WNOHANG))? {
+            WaitStatus::StillAlive => Ok(None),
+            status => Ok(Some(self.reaped(status))),
+        }
+    }
+
+    fn reaped(&self, status: WaitStatus) -> WaitStatus {
+        self.status.set(Some(status));
+        status
+    }
+
+    /// SIGTERM, then SIGKILL when it is still running after `timeout`, and
+    /// reap it. What it started is left to [`Child::stop_scope`].
+    pub fn stop(&self, timeout: Duration) -> Result<WaitStatus> {
+        self.signal(Signal::SIGTERM)?;
+
+        let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+        match nix::poll::poll(&mut [PollFd::new(self.as_fd(), PollFlags::POLLIN)], timeout) {
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        if self.try_wait()?.is_none() {
+            self.signal(Signal::SIGKILL)?;
+        }
+
+        self.wait()
+    }
+
+    /// Signalling a process that already exited is not an error.
+    pub fn signal(&self, signal: Signal) -> Result<()> {
+        let sent = Errno::result(unsafe {
+            nix::libc::syscall(
+                nix::libc::SYS_pidfd_send_signal,
+                self.pidfd.as_raw_fd(),
+                signal as nix::libc::c_int,
+                std::ptr::null::<nix::libc::siginfo_t>(),
+                0,
+            )
+        });
+
+        match sent {
+            Err(Errno::ESRCH) => Ok(()),
+            result => Ok(result.map(drop)?),
+        }
+    }
+
+    /// Stop what the child left running once it has been waited for, see
+    /// [`Scope::stop`]. Nothing is tracked unless it was isolated.
+    pub fn stop_scope(&self, timeout: Duration) -> Result<Vec<Leftover>> {
+        match &self.scope {
+            Some(scope) => scope.stop(timeout),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+impl AsFd for Child {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.pidfd.as_fd()
+    }
+}
+
+fn pidfd_open(pid: Pid) -> Result<OwnedFd> {
+    let fd =
+        Errno::result(unsafe { nix::libc::syscall(nix::libc::SYS_pidfd_open, pid.as_raw(), 0) })?;
+
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_failure_reaches_the_parent() {
+        let Err(Error::Spawn(message)) = Spec::new("/nonexistent/niri").spawn() else {
+            panic!("spawning a missing program should fail");
+        };
+
+        assert_eq!(
+            message,
+            "/nonexistent/niri: exec failed: ENOENT: No such file or directory"
+        );
+    }
+
+    #[test]
+    fn child_runs_with_env_and_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+
+        let child = Spec::new("sh")
+            .args(["-c", "echo \"$GREETING $(pwd)\" > out"])
+            .env(vec![CString::new("GREETING=hello").unwrap()])
+            .cwd(dir.path())
+            .spawn()
+            .unwrap();
+
+        assert!(matches!(child.wait().unwrap(), WaitStatus::Exited(_, 0)));
+        assert_eq!(
+            std::fs::read_to_string(out).unwrap(),
+            format!("hello {}\n", dir.path().canonicalize().unwrap().display())
+        );
+    }
+
+    #[test]
+    fn stopping_the_scope_kills_what_ignores_sigterm() {
+        let child = Spec::new("sh")
+            .args(["-c", "sleep 60 & (trap '' TERM; exec sleep 61) & sleep 0.2"])
+            .isolate()
+            .spawn()
+            .unwrap();
+
+        child.wait().unwrap();
+        let killed = child.stop_scope(Duration::from_millis(300)).unwrap();
+
+        assert_eq!(killed.len(), 1);
+        assert_eq!(killed[0].name, "sleep");
+    }
+
+    #[test]
+    fn stopping_kills_a_child_that_ignores_sigterm() {
+        let child = Spec::new("sh")
+            .args(["-c", "trap '' TERM; while :; do sleep 1; done"])
+            .isolate()
+            .spawn()
+            .unwrap();
+        // Give it the time to set up the trap.
+        std::thread::sleep(Duration::from_millis(100));
+
+        let status = child.stop(Duration::from_millis(200)).unwrap();
+        assert_eq!(
+            status,
+            WaitStatus::Signaled(child.pid(), Signal::SIGKILL, false)
+        );
+        assert_eq!(child.wait().unwrap(), status);
+
+        let killed = child.stop_scope(Duration::from_millis(300)).unwrap();
+        assert_eq!(killed.len(), 1);
+        assert_eq!(killed[0].name, "sleep");
+    }
+
+    #[test]
+    fn failed_setup_names_the_step() {
+        let Err(Error::Spawn(message)) = Spec::new("true").cwd("/nonexistent").spawn() else {
+            panic!("changing to a missing directory should fail");
+        };
+
+        assert!(message.starts_with("true: changing directory failed: ENOENT"));
+    }
+}
@@ -1,213 +1,699 @@
 use std::{
     ffi::{CString, OsStr},
-    io::Write,
+    os::fd::AsFd,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
-use authkit::{AuthnFlags, BaseFlags, CredAction, Pam};
+use authkit::{
+    AuthnFlags, BaseFlags, CredAction, Pam,
+    tty::{Claim, Mode, Terminal, Vt},
+};
+use nix::{
+    poll::{PollFd, PollFlags, PollTimeout},
+    sys::wait::WaitStatus,
+};
 
 use crate::{
-    config::{NIRI_GREETER_CONFIG, NIRI_SESSION_CONFIG},
-    niri,
+    config::{Config, Systemd},
+    console, environment, greetd, greeter, ipc,
+    kdl::{self, Node},
+    last::{self, Last},
+    log, niri, notify, prompt, sessions,
+    signals::Signals,
+    spawn::{self, Child},
+    supervisor::{self, Greeted},
+    systemd, sysuser, utmp, xwayland,
 };
 
 use super::{Error, Result};
 
-macro_rules! try_cstrings {
-    ($($s:expr),* $(,)?) => {
-        (|| -> core::result::Result<_, std::ffi::NulError> {
-            Ok(($(std::ffi::CString::new($s)?,)*))
-        })()
+fn get_current_user() -> Result<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .map_err(|e| {
+            log::error!("{e}");
+            Error::UnknownCurrentUserHost
+        })
+}
+
+const GREETER_SOCKET: &str = "/run/rilm/greeter.sock";
+const GREETD_SOCKET: &str = "/run/rilm/greetd.sock";
+/// Exists once the single-shot autologin happened, until the next boot.
+const AUTOLOGIN_DONE: &str = "/run/rilm/autologin-done";
+
+/// Loads the configuration, again on SIGHUP.
+pub type Loader = Box<dyn Fn() -> Result<Config>>;
+
+pub fn start_display_tty(tty_number: Option<u16>, load: Loader) -> Result<()> {
+    let config = initial_config(&load);
+    let tty_number = tty_number.unwrap_or(config.display.tty);
+    notify::init();
+
+    log::info!("Starting RILM display in TTY mode on tty{}", tty_number);
+    log::info!("Running as root on tty{}", tty_number);
+
+    let greeter = sysuser::ensure(&sysuser::Account::greeter(&config.greeter), Path::new("/"))?;
+
+    let mut tty = Tty {
+        tty_number,
+        greeter,
+        config,
+        load,
+        signals: Signals::install()?,
+        single_shot: false,
+        record: None,
     };
+    notify::ready();
+    let result = supervisor::run(&mut tty);
+    notify::stopping();
+
+    // Whatever ran last may have left it in graphics mode.
+    if let Err(e) = Vt::open(tty_number).and_then(|mut vt| vt.set_mode(Mode::Text)) {
+        log::warning!("Couldn't restore tty{tty_number}: {e}");
+    }
+
+    tty.signals.finish(result)
 }
 
-macro_rules! forke {
-    ($ENV:expr, $($arg:expr),* $(,)?) => {{
-        let bin = std::ffi::CString::new(std::env::current_exe()?.to_str().ok_or(crate::error::Error::ToStrError)?)?;
+/// A real seat: greeter and sessions run as their own users, with PAM
+/// sessions, on a VT.
+struct Tty {
+    tty_number: u16,
+    greeter: sysuser::Entry,
+    config: Config,
+    load: Loader,
+    signals: Signals,
+    /// Whether the login being opened is the single-shot autologin, which
+    /// only counts once its session opened.
+    single_shot: bool,
+    /// The running session's login record.
+    record: Option<utmp::Record>,
+}
 
-        let args = [
-            &bin,
-            $(&std::ffi::CString::new($arg)?,)*
-        ];
+impl supervisor::Backend for Tty {
+    type Login = greeter::Login;
+    type Session = Child;
 
-        match unsafe { nix::unistd::fork() } {
-            Ok(nix::unistd::ForkResult::Parent { child }) => child,
-            Ok(nix::unistd::ForkResult::Child) => {
-                if nix::unistd::execve::<&std::ffi::CString, std::ffi::CString>(&bin, &args, $ENV).is_err() {
-                    std::process::exit(1);
-                }
+    const HAS_CONSOLE: bool = true;
 
-                unreachable!("SOMETHING BAD HAPPENED")
-            }
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                std::process::exit(1);
-            }
+    fn stopping(&mut self) -> Result<bool> {
+        handle_signals(&self.signals, &mut self.config, &self.load)
+    }
+
+    fn autologin(&mut self) -> Result<Option<greeter::Login>> {
+        let autologin = &self.config.autologin;
+        let Some(user) = &autologin.user else {
+            return Ok(None);
+        };
+
+        if autologin.once && Path::new(AUTOLOGIN_DONE).exists() {
+            return Ok(None);
         }
-    }};
-}
 
-macro_rules! fork {
-    ($($arg:expr),* $(,)?) => {{
-        let bin = std::ffi::CString::new(std::env::current_exe()?.to_str().ok_or(crate::error::Error::ToStrError)?)?;
+        if autologin.delay > 0
+            && !start_countdown(self.tty_number, user, autologin.delay, &self.signals)?
+        {
+            return Ok(None);
+        }
+
+        let session = match &autologin.session {
+            Some(id) => sessions::discover()
+                .into_iter()
+                .find(|session| &session.id == id)
+                .ok_or(Error::Config(format!("unknown autologin session {id}")))?,
+            None => sessions::Session::builtin(),
+        };
+
+        let mut txn = Pam::start(
+            autologin.pam_service.as_str().into(),
+            user.as_str().into(),
+            "".into(),
+        )?;
+        txn.authenticate(AuthnFlags::empty())?;
+        txn.account_management(AuthnFlags::empty())?;
+
+        self.single_shot = autologin.once;
+
+        Ok(Some(greeter::Login {
+            user: user.clone(),
+            txn,
+            command: session.exec.clone(),
+            env: session.env(),
+        }))
+    }
+
+    fn greeter(&mut self) -> Result<Greeted<greeter::Login>> {
+        Ok(
+            match run_greeter(self.tty_number, &self.greeter, &self.config, &self.signals)? {
+                greeter::Outcome::Authenticated(login) => Greeted::Authenticated(login),
+                greeter::Outcome::Exited(status) => Greeted::Exited(status),
+                greeter::Outcome::Interrupted => Greeted::Interrupted,
+            },
+        )
+    }
+
+    fn back_off(&mut self, delay: Duration) {
+        let mut fds = [PollFd::new(self.signals.as_fd(), PollFlags::POLLIN)];
+        let _ = nix::poll::poll(
+            &mut fds,
+            PollTimeout::try_from(delay).unwrap_or(PollTimeout::MAX),
+        );
+    }
+
+    fn console(&mut self) -> Result<Option<greeter::Login>> {
+        Ok(
+            match start_console_greeter(self.tty_number, &self.config, &self.signals)? {
+                console::Outcome::RetryGraphical => None,
+                console::Outcome::Authenticated { user, txn } => Some(greeter::Login {
+                    user,
+                    txn,
+                    command: Vec::new(),
+                    env: Vec::new(),
+                }),
+            },
+        )
+    }
+
+    fn open_session(&mut self, login: &mut greeter::Login) -> Result<()> {
+        let single_shot = std::mem::take(&mut self.single_shot);
+
+        let user = nix::unistd::User::from_name(&login.user)?
+            .ok_or(Error::UnknownUserWithName(login.user.clone()))?;
+
+        let txn = &mut login.txn;
+
+        txn.items_mut()
+            .set_tty_name(Some(OsStr::new(&format!("tty{}", self.tty_number))))?;
+        txn.env_mut()
+            .insert("XDG_VTNR", self.tty_number.to_string());
+
+        txn.env_mut().insert("XDG_SEAT", "seat0");
+        txn.env_mut().insert("XDG_SESSION_CLASS", "user");
+        txn.env_mut().insert("XDG_SESSION_TYPE", "wayland");
 
-        let args = [
-            &bin,
-            $(&std::ffi::CString::new($arg)?,)*
-        ];
+        for (key, val) in login.env.iter().filter_map(|var| var.split_once('=')) {
+            txn.env_mut().insert(key, val);
+        }
+
+        txn.env_mut().insert("USER", &login.user);
+        txn.env_mut().insert("LOGNAME", &login.user);
+        txn.env_mut().insert("HOME", &user.dir);
+        txn.env_mut().insert("SHELL", &user.shell);
 
-        match unsafe { nix::unistd::fork() } {
-            Ok(nix::unistd::ForkResult::Parent { child }) => child,
-            Ok(nix::unistd::ForkResult::Child) => {
-                if nix::unistd::execv::<&std::ffi::CString>(&bin, &args).is_err() {
-                    std::process::exit(1);
-                }
+        txn.open_session(BaseFlags::empty())?;
+        if let Err(e) = txn.setcred(CredAction::Establish) {
+            txn.close_session(BaseFlags::empty())?;
+            return Err(e.into());
+        }
 
-                unreachable!("SOMETHING BAD HAPPENED")
+        if single_shot && let Err(e) = mark_autologin_done() {
+            log::warning!("Couldn't mark the autologin as done: {e}");
+        }
+
+        if self.config.greeter.remember {
+            let last = Last {
+                user: Some(login.user.clone()),
+                session: env_value(&login.env, "XDG_SESSION_DESKTOP"),
+                layout: env_value(&pam_env_strings(txn)?, "XKB_DEFAULT_LAYOUT"),
+            };
+
+            if let Err(e) = last.save(Path::new(last::LAST_PATH)) {
+                log::warning!("Couldn't remember the last login: {e}");
             }
-            Err(e) => {
-                eprintln!("Error: {}", e);
-                std::process::exit(1);
+        }
+
+        log::set(log::USER, Some(&login.user));
+        log::set(
+            log::SESSION_ID,
+            env_value(&pam_env_strings(txn)?, "XDG_SESSION_ID").as_deref(),
+        );
+
+        Ok(())
+    }
+
+    fn spawn_session(&mut self, login: &greeter::Login) -> Result<Child> {
+        let user = nix::unistd::User::from_name(&login.user)?
+            .ok_or(Error::UnknownUserWithName(login.user.clone()))?;
+
+        let env = environment::login(&user, &pam_env_strings(&login.txn)?, &self.config.session);
+
+        let mut spec = login_command(&user, session_args(&login.command), &self.config)?
+            .env(env.cstrings()?)
+            .user(user.uid, user.gid, groups(&login.user, user.gid)?)
+            .cwd(&user.dir)
+            .tty(format!("/dev/tty{}", self.tty_number))
+            .isolate();
+
+        for (resource, [soft, hard]) in self.config.session.limits.resources() {
+            spec = spec.rlimit(resource, soft, hard);
+        }
+
+        let session = spec.spawn()?;
+
+        if self.config.accounting.enable {
+            let record = utmp::Record {
+                line: format!("tty{}", self.tty_number),
+                user: login.user.clone(),
+                pid: session.pid().as_raw(),
+                time: SystemTime::now(),
+            };
+
+            if let Err(e) = utmp::login(
+                &record,
+                &self.config.accounting,
+                &self.config.programs.lastlog2,
+            ) {
+                log::warning!("Couldn't record the login: {e}");
             }
+            self.record = Some(record);
         }
-    }};
-}
 
-fn get_current_user() -> Result<String> {
-    std::env::var("USER")
-        .or_else(|_| std::env::var("USERNAME"))
-        .map_err(|e| {
-            eprintln!("{e}");
-            Error::UnknownCurrentUserHost
-        })
-}
+        Ok(session)
+    }
+
+    fn wait_session(&mut self, session: Child) -> Result<WaitStatus> {
+        end_session(session, &self.config, &self.signals)
+    }
+
+    fn close_session(&mut self, mut login: greeter::Login) -> Result<()> {
+        log::set(log::USER, None);
+        log::set(log::SESSION_ID, None);
+
+        if let Some(record) = self.record.take()
+            && let Err(e) = utmp::logout(&record, &self.config.accounting)
+        {
+            log::warning!("Couldn't record the logout: {e}");
+        }
 
-pub fn start_display_tty(tty_number: u16) -> Result<()> {
-    println!("Starting RILM display in TTY mode on tty{}", tty_number);
-    println!("Running as root on tty{}", tty_number);
+        login.txn.setcred(CredAction::Delete)?;
+        login.txn.close_session(BaseFlags::empty())?;
+
+        Ok(())
+    }
+}
 
-    let mut txn = Pam::start("rilm".into(), "greeter".into(), "".into())?;
+fn run_greeter(
+    tty_number: u16,
+    greeter_user: &sysuser::Entry,
+    config: &Config,
+    signals: &Signals,
+) -> Result<greeter::Outcome> {
+    let name = config.greeter.user.as_str();
+
+    let listener = greeter::bind(Path::new(GREETER_SOCKET), Some(greeter_user.uid))?;
+    let greetd = greeter::bind(Path::new(GREETD_SOCKET), Some(greeter_user.uid))?;
+
+    let mut txn = Pam::start(
+        config.greeter.pam_service.as_str().into(),
+        name.into(),
+        "".into(),
+    )?;
     txn.authenticate(AuthnFlags::empty())?;
     txn.account_management(AuthnFlags::empty())?;
 
-    txn.items_mut().set_tty_name(Some(&OsStr::new("tty3")))?;
-    txn.env_mut().insert("XDG_VTNR", "3");
+    txn.items_mut()
+        .set_tty_name(Some(OsStr::new(&format!("tty{tty_number}"))))?;
+    txn.env_mut().insert("XDG_VTNR", tty_number.to_string());
 
     txn.env_mut().insert("XDG_SEAT", "seat0");
     txn.env_mut().insert("XDG_SESSION_CLASS", "greeter");
-    txn.env_mut().insert("USER", "greeter");
-    txn.env_mut().insert("LOGNAME", "greeter");
-    txn.env_mut().insert("HOME", "");
-    txn.env_mut().insert("SHELL", "/bin/bash");
+    txn.env_mut().insert("USER", name);
+    txn.env_mut().insert("LOGNAME", name);
+    txn.env_mut().insert("HOME", &greeter_user.home);
+    txn.env_mut().insert("SHELL", &greeter_user.shell);
     txn.env_mut().insert("TERM", "linux");
+    txn.env_mut().insert(ipc::SOCKET_ENV, GREETER_SOCKET);
+    txn.env_mut().insert(greetd::SOCKET_ENV, GREETD_SOCKET);
+
+    match config.greeter.remember {
+        true => {
+            for (key, val) in Last::load(Path::new(last::LAST_PATH)).env() {
+                txn.env_mut().insert(key, val);
+            }
+        }
+        false => Last::forget(Path::new(last::LAST_PATH))?,
+    }
 
     txn.open_session(BaseFlags::empty())?;
     txn.setcred(CredAction::Establish)?;
 
-    // let fd = authkit::tty::open(3)?;
-    // let current = authkit::tty::current(&fd);
-    // if current != 3 {
-    //     authkit::tty::switch(&fd, 3);
-    // }
+    let outcome = rilm(greeter_args(&config.greeter.command))?
+        .env(pam_env(&txn)?)
+        .user(
+            greeter_user.uid,
+            greeter_user.gid,
+            groups(name, greeter_user.gid)?,
+        )
+        .cwd(&greeter_user.home)
+        .tty(format!("/dev/tty{tty_number}"))
+        .isolate()
+        .spawn()
+        .and_then(|child| {
+            greeter::serve(
+                &[
+                    (&listener, greeter::Protocol::Native),
+                    (&greetd, greeter::Protocol::Greetd),
+                ],
+                &child,
+                signals,
+                greeter_user.uid,
+                &config.session.pam_service,
+            )
+        });
+
+    // Every step runs even when one before it failed, the first failure is
+    // returned once they all did, after one from serving the greeter.
+    let teardown: [Result<()>; 4] = [
+        txn.setcred(CredAction::Delete).map_err(Error::from),
+        txn.close_session(BaseFlags::empty()).map_err(Error::from),
+        std::fs::remove_file(GREETER_SOCKET).map_err(Error::from),
+        std::fs::remove_file(GREETD_SOCKET).map_err(Error::from),
+    ];
+
+    let mut failed = None;
+    for e in teardown.into_iter().filter_map(Result::err) {
+        log::error!("Failed to clean up after the greeter: {e}");
+        failed.get_or_insert(e);
+    }
+
+    outcome.and_then(|outcome| failed.map_or(Ok(outcome), Err))
+}
+
+fn start_console_greeter(
+    tty_number: u16,
+    config: &Config,
+    signals: &Signals,
+) -> Result<console::Outcome> {
+    let mut claim = Claim::new(Vt::open(tty_number)?)?;
+    claim.terminal_mut().set_mode(Mode::Text)?;
+
+    let outcome = console::run(
+        claim.terminal(),
+        &config.session.pam_service,
+        signals.as_fd(),
+    );
+
+    claim.release()?;
+    outcome
+}
+
+fn mark_autologin_done() -> Result<()> {
+    if let Some(dir) = Path::new(AUTOLOGIN_DONE).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    Ok(std::fs::write(AUTOLOGIN_DONE, "")?)
+}
+
+fn start_countdown(tty_number: u16, user: &str, delay: u64, signals: &Signals) -> Result<bool> {
+    let mut claim = Claim::new(Vt::open(tty_number)?)?;
+    claim.terminal_mut().set_mode(Mode::Text)?;
+
+    let proceed = console::countdown(claim.terminal(), user, delay, signals.as_fd());
+
+    claim.release()?;
+    proceed
+}
+
+/// rilm itself, running `args`.
+fn rilm(args: Vec<String>) -> Result<spawn::Spec> {
+    Ok(spawn::Spec::new(std::env::current_exe()?).args(args))
+}
+
+/// rilm running `args` for `user`, through their login shell if configured.
+fn login_command(
+    user: &nix::unistd::User,
+    args: Vec<String>,
+    config: &Config,
+) -> Result<spawn::Spec> {
+    if !config.session.login_shell {
+        return rilm(args);
+    }
+
+    let exe = std::env::current_exe()?;
+    let exe = exe.to_str().ok_or(Error::ToStrError)?;
+
+    let command = environment::login_shell(
+        &user.shell,
+        &[exe.to_string()]
+            .into_iter()
+            .chain(args)
+            .collect::<Vec<_>>(),
+    );
+
+    Ok(spawn::Spec::new(&command[0]).args(&command[1..]))
+}
+
+fn greeter_args(command: &[String]) -> Vec<String> {
+    let mut args = vec![String::from("start"), String::from("greeter")];
+
+    if !command.is_empty() {
+        args.push(String::from("--"));
+        args.extend(command.iter().cloned());
+    }
+
+    args
+}
+
+fn session_args(command: &[String]) -> Vec<String> {
+    let mut args = vec![String::from("start"), String::from("session")];
 
-    // authkit::tty::take(&fd);
+    if !command.is_empty() {
+        args.push(String::from("--"));
+        args.extend(command.iter().cloned());
+    }
 
-    let env = txn
-        .env()
+    args
+}
+
+/// [`session_args`] for the session that runs under the user manager.
+fn managed_session_args(command: &[String]) -> Vec<String> {
+    let mut args = session_args(command);
+    args.insert(2, String::from("--managed"));
+    args
+}
+
+fn pam_env(txn: &Pam) -> Result<Vec<CString>> {
+    pam_env_strings(txn)?
+        .into_iter()
+        .map(|var| Ok(CString::new(var)?))
+        .collect()
+}
+
+fn pam_env_strings(txn: &Pam) -> Result<Vec<String>> {
+    txn.env()
         .iter()
         .map(|(key, val)| {
-            CString::new(format!(
+            Ok(format!(
                 "{}={}",
-                key.to_str().unwrap(),
-                val.to_str().unwrap()
+                key.to_str().ok_or(Error::ToStrError)?,
+                val.to_str().ok_or(Error::ToStrError)?
             ))
-            .unwrap()
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+/// The value of `key` in `KEY=VALUE` variables.
+fn env_value(env: &[String], key: &str) -> Option<String> {
+    env.iter()
+        .filter_map(|var| var.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, val)| val.to_string())
+}
 
-    let child = forke!(&env, "start", "greeter");
+/// The current environment with `overrides` (as `KEY=VALUE`) applied.
+fn inherited_env(overrides: &[String]) -> Result<Vec<CString>> {
+    let overridden = |key: &str| {
+        overrides
+            .iter()
+            .any(|var| var.split_once('=').is_some_and(|(k, _)| k == key))
+    };
+
+    std::env::vars_os()
+        .map(|(key, val)| (key.into_string(), val.into_string()))
+        .filter_map(|(key, val)| Some((key.ok()?, val.ok()?)))
+        .filter(|(key, _)| !overridden(key))
+        .map(|(key, val)| format!("{key}={val}"))
+        .chain(overrides.iter().cloned())
+        .map(|var| Ok(CString::new(var)?))
+        .collect()
+}
+
+/// Take in pending signals, reloading the configuration if asked to.
+/// `true` once the display should stop.
+/// The configuration the display starts with, logging set up from it. A
+/// broken one must not keep the greeter and the console from showing up, so
+/// the defaults stand in for it, as the old one does when a reload fails.
+fn initial_config(load: &Loader) -> Config {
+    let (config, error) = match load() {
+        Ok(config) => (config, None),
+        Err(e) => (Config::default(), Some(e)),
+    };
+
+    log::init(&config.log);
+    if let Some(e) = error {
+        log::error!("Starting with the default configuration: {e}");
+    }
 
-    loop {
-        match nix::sys::wait::waitpid(child, None) {
-            Err(nix::errno::Errno::EINTR) => continue,
-            Err(e) => {
-                eprintln!("waitpid failed: {e}");
-                break;
+    config
+}
+
+fn handle_signals(signals: &Signals, config: &mut Config, load: &Loader) -> Result<bool> {
+    signals.read()?;
+
+    if signals.take_reload() {
+        match load() {
+            Ok(loaded) => {
+                *config = loaded;
+                log::info!("Configuration reloaded");
             }
-            Ok(_) => break,
+            Err(e) => log::warning!("Keeping the current configuration: {e}"),
         }
     }
 
-    // authkit::tty::switch(&fd, current);
-    // authkit::tty::close(fd);
-
-    txn.close_session(BaseFlags::empty())?;
-
-    todo!(
-        r#"
-            - [ ] Check for a user named 'greeter'. If not present create it and add it to the video group.
-            - [ ] Open a UnixSocket only readable for root, writable for user "greeter"
-            - [ ] Open a PAM session for user "greeter"
-            - [ ] Fork, exec this program again with commands 'rilm start greeter --user "greeter"'
-            - ...
-            - [ ] Wait for it to finish
-            - ...
-            - [ ] End the PAM session
-            - [ ] Once it's done we read the socket, there should be a pair of (user, cred),
-            - [ ] Close the socket
-            - [ ] Open a PAM session for this user, add credentials to it
-            - [ ] Fork, exec this program again with commands 'rilm start session --user <user>'
-            - ...
-            - [ ] Wait for it to finish
-            - ...
-            - [ ] End the PAM session
-            - [ ] Once it's done repeat this program by running 'rilm start tty' (or 'rilm start display tty')
-            "#
-    )
+    let stopping = signals.stop().is_some();
+    if stopping {
+        notify::stopping();
+    }
+
+    Ok(stopping)
 }
 
-pub fn start_display_winit() -> Result<()> {
+/// Wait for the session leader, then stop whatever it left behind.
+fn end_session(session: Child, config: &Config, signals: &Signals) -> Result<WaitStatus> {
+    let status = signals.wait(&session)?;
+
+    let timeout = config.session.stop_timeout;
+    let killed = session.stop_scope(Duration::from_secs(timeout))?;
+    if !killed.is_empty() {
+        let killed = killed.iter().map(ToString::to_string).collect::<Vec<_>>();
+        log::warning!(
+            "Killed what the session left running after {timeout}s: {}",
+            killed.join(", ")
+        );
+    }
+
+    Ok(status)
+}
+
+/// Supplementary groups of `user`, as initgroups would set them.
+fn groups(user: &str, gid: nix::unistd::Gid) -> Result<Vec<nix::unistd::Gid>> {
+    Ok(nix::unistd::getgrouplist(&CString::new(user)?, gid)?)
+}
+
+pub fn start_display_winit(load: Loader) -> Result<()> {
+    let config = initial_config(&load);
+    notify::init();
+
     let current_user = get_current_user()?;
-    println!("Starting RILM display in Winit mode");
-    println!("Running in simulated window under user: {}", current_user);
+    log::info!("Starting RILM display in Winit mode");
+    log::info!("Running in simulated window under user: {}", current_user);
+
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+
+    let mut winit = Winit {
+        socket: runtime_dir.join("rilm-greeter.sock"),
+        greetd_socket: runtime_dir.join("rilm-greetd.sock"),
+        config,
+        load,
+        signals: Signals::install()?,
+    };
+    notify::ready();
+    let result = supervisor::run(&mut winit);
+    notify::stopping();
 
-    let child = fork!("start", "greeter");
+    winit.signals.finish(result)
+}
 
-    loop {
-        match nix::sys::wait::waitpid(child, None) {
-            Err(nix::errno::Errno::EINTR) => continue,
-            Err(e) => {
-                eprintln!("waitpid failed: {e}");
-                break;
-            }
-            Ok(_) => break,
-        }
+/// A nested window: everything runs as the current user, without PAM
+/// sessions, and closing the greeter ends the display.
+struct Winit {
+    socket: PathBuf,
+    greetd_socket: PathBuf,
+    config: Config,
+    load: Loader,
+    signals: Signals,
+}
+
+impl supervisor::Backend for Winit {
+    type Login = greeter::Login;
+    type Session = Child;
+
+    const HAS_CONSOLE: bool = false;
+
+    fn stopping(&mut self) -> Result<bool> {
+        handle_signals(&self.signals, &mut self.config, &self.load)
     }
 
-    todo!(
-        r#"
-            - [ ] Open a UnixSocket only readable for <current_user>, writable for user <current_user>
-            - [x] Fork, exec this program again with commands 'rilm start greeter'
-            - ...
-            - [ ] Wait for UnixSocket pair of login/cred
-            - [ ] If ok, then send close signal wait for close
-            - [ ] If not, then send retry signal, repeat
-            - ...
-            - [ ] Once it's done we read the socket, there should be a pair of (user, cred),
-            - [ ] Close the socket
-            - [ ] Open a PAM authentication for this user, add credentials to it
-            - [ ] Fork, exec this program again with commands 'rilm start session'
-            - ...
-            - [ ] Wait for it to finish
-            - ...
-            - [ ] Once it's done repeat this program by running 'rilm start winit' (or 'rilm start display tty')
-            "#
-    )
+    /// A nested display is for trying the greeter out.
+    fn autologin(&mut self) -> Result<Option<greeter::Login>> {
+        Ok(None)
+    }
+
+    fn greeter(&mut self) -> Result<Greeted<greeter::Login>> {
+        let listener = greeter::bind(&self.socket, None)?;
+        let greetd = greeter::bind(&self.greetd_socket, None)?;
+
+        let env = inherited_env(&[
+            format!("{}={}", ipc::SOCKET_ENV, self.socket.display()),
+            format!("{}={}", greetd::SOCKET_ENV, self.greetd_socket.display()),
+        ])?;
+
+        let child = rilm(greeter_args(&self.config.greeter.command))?
+            .env(env)
+            .isolate()
+            .spawn()?;
+        let outcome = greeter::serve(
+            &[
+                (&listener, greeter::Protocol::Native),
+                (&greetd, greeter::Protocol::Greetd),
+            ],
+            &child,
+            &self.signals,
+            nix::unistd::Uid::current(),
+            &self.config.session.pam_service,
+        )?;
+        std::fs::remove_file(&self.socket)?;
+        std::fs::remove_file(&self.greetd_socket)?;
+
+        Ok(match outcome {
+            greeter::Outcome::Authenticated(login) => Greeted::Authenticated(login),
+            greeter::Outcome::Exited(status) => Greeted::Exited(status),
+            greeter::Outcome::Interrupted => Greeted::Interrupted,
+        })
+    }
+
+    fn console(&mut self) -> Result<Option<greeter::Login>> {
+        Ok(None)
+    }
+
+    fn open_session(&mut self, _: &mut greeter::Login) -> Result<()> {
+        Ok(())
+    }
+
+    fn spawn_session(&mut self, login: &greeter::Login) -> Result<Child> {
+        rilm(session_args(&login.command))?
+            .env(inherited_env(&login.env)?)
+            .isolate()
+            .spawn()
+    }
+
+    fn wait_session(&mut self, session: Child) -> Result<WaitStatus> {
+        end_session(session, &self.config, &self.signals)
+    }
+
+    fn close_session(&mut self, _: greeter::Login) -> Result<()> {
+        Ok(())
+    }
 }
 
-pub fn start_greeter(user: Option<String>) -> Result<()> {
+pub fn start_greeter(user: Option<String>, command: Vec<String>, config: &Config) -> Result<()> {
     let current_user = get_current_user()?;
 
-    println!(
+    log::info!(
         "Starting RILM greeter for user: {}",
         user.as_ref().unwrap_or(&current_user)
     );
@@ -225,75 +711,255 @@ pub fn start_greeter(user: Option<String>) -> Result<()> {
         nix::unistd::setuid(user.uid).map_err(Error::UserError)?;
     }
 
-    let bin = std::env::current_exe()?;
+    capture_output("greeter", config);
+
+    let command = match command.is_empty() {
+        true => vec![
+            std::env::current_exe()?
+                .to_str()
+                .ok_or(Error::ToStrError)?
+                .to_string(),
+            String::from("start"),
+            String::from("greeter"),
+            String::from("--prompt"),
+        ],
+        false => command,
+    };
 
-    niri::launch!(
-        NIRI_GREETER_CONFIG,
-        bin.to_str().ok_or(crate::error::Error::ToStrError)?,
-        "start",
-        "greeter",
-        "--prompt"
-    );
+    let mut config = config.clone();
+    if config.niri.keyboard.layout.is_none() {
+        config.niri.keyboard.layout = std::env::var(last::LAYOUT_ENV).ok();
+    }
 
-    todo!(
-        r#"
-            < parent process should have filled the correct ENV vars to open a niri session >
+    niri::launch!(config.greeter_niri_config()?, &config.programs; command)
+}
 
-            - [x] if <user> != None, change for this user
-            - [x] do an `execv` with niri and the greeter config
-            "#
-    )
+/// What [`environment::login`] gives `user` without a PAM session.
+pub fn print_env(user: Option<String>, config: &Config) -> Result<()> {
+    let name = match user {
+        Some(name) => name,
+        None => get_current_user()?,
+    };
+
+    let user = nix::unistd::User::from_name(&name)?.ok_or(Error::UnknownUserWithName(name))?;
+
+    for var in environment::login(&user, &[], &config.session).strings() {
+        println!("{var}");
+    }
+
+    Ok(())
 }
 
 pub fn start_greeter_prompt() -> Result<()> {
-    print!("login: ");
-    std::io::stdout().flush()?;
-    let mut login = String::new();
-    std::io::stdin().read_line(&mut login)?;
-    let login = login.trim_end();
-
-    println!("Your login is: {}", login);
-
-    std::thread::park();
-
-    todo!(
-        r#"
-            - [ ] ask for login / credentials
-            - [ ] send to the root this pair on a unixsocket
-            - [ ] park until the root closes the greeter
-            "#
-    )
+    prompt::run()
 }
 
-pub fn start_session(user: Option<String>) -> Result<()> {
+pub fn start_session(
+    user: Option<String>,
+    command: Vec<String>,
+    managed: bool,
+    config: &Config,
+) -> Result<()> {
     let current_user = get_current_user()?;
 
-    println!(
+    log::info!(
         "Starting RILM session for user: {}",
         user.as_ref().unwrap_or(&current_user)
     );
 
+    // Become the user and start over with their login environment.
     if let Some(username) = user {
         let user = nix::unistd::User::from_name(&username)
             .ok()
             .flatten()
             .ok_or(Error::UnknownUserWithName(username.clone()))?;
 
-        let cuser = std::ffi::CString::new(username.as_str()).map_err(Error::NulError)?;
+        let inherited = environment::Environment::inherited().strings();
+        let env = environment::login(&user, &inherited, &config.session);
 
-        nix::unistd::initgroups(&cuser, user.gid).map_err(Error::UserError)?;
-        nix::unistd::setgid(user.gid).map_err(Error::UserError)?;
-        nix::unistd::setuid(user.uid).map_err(Error::UserError)?;
+        return Err(login_command(&user, session_args(&command), config)?
+            .env(env.cstrings()?)
+            .user(user.uid, user.gid, groups(&username, user.gid)?)
+            .cwd(&user.dir)
+            .exec());
     }
 
-    niri::launch!(NIRI_SESSION_CONFIG, "/usr/bin/nu");
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+
+    if !managed {
+        capture_output("session", config);
+
+        let session = std::env::var("XDG_SESSION_DESKTOP")
+            .unwrap_or_else(|_| sessions::Session::builtin().id);
+
+        let x11 = std::env::var("XDG_SESSION_TYPE").is_ok_and(|kind| kind == "x11");
+
+        // Lives until the session returns, if it doesn't replace this process.
+        let _xwayland = match !x11 && config.xwayland.enabled(&current_user, &session) {
+            true => Some(start_xwayland(config)?),
+            false => None,
+        };
+
+        let manager = systemd::UserManager::new(&config.programs.systemctl);
+        let keys = systemd::session_vars();
+
+        match config.session.systemd {
+            Systemd::Service if command.is_empty() => {
+                // niri.service takes no arguments, the session command is
+                // started from the config instead.
+                let mut niri_config = config.session_niri_config(home.as_deref())?;
+                niri_config.push_str(&kdl::document(&[Node::new("spawn-at-startup").args(
+                    config
+                        .programs
+                        .terminal
+                        .iter()
+                        .chain(&config.session.command),
+                )]));
+
+                let (tmp, notice) = niri::prepare(&niri_config, &config.programs.niri)?;
+                let vars = [format!("NIRI_CONFIG={}", tmp.path().display())]
+                    .into_iter()
+                    .chain(notice)
+                    .collect::<Vec<_>>();
+
+                return systemd::as_service(&manager, &keys, &vars);
+            }
+            Systemd::Target | Systemd::Service => {
+                let status = systemd::with_target(&manager, &keys, || {
+                    rilm(managed_session_args(&command))?.spawn()?.wait()
+                })?;
+                log::info!("Session exited with {status:?}");
+
+                return Ok(());
+            }
+            Systemd::Off => {}
+        }
+    }
 
-    todo!(
-        r#"
-            < parent process should have filled the correct ENV vars to open a niri session >
+    if !command.is_empty() {
+        return exec_session(command, config);
+    }
 
-            - [x] if <user> != None, change for this user
-            - [x] do an `execv` with niri and the session config
-            "#
+    // Under graphical-session.target niri shares WAYLAND_DISPLAY with user
+    // services itself.
+    let flags: &[&str] = match config.session.systemd {
+        Systemd::Off => &[],
+        _ => &["--session"],
+    };
+
+    niri::launch!(
+        config.session_niri_config(home.as_deref())?,
+        &config.programs,
+        flags;
+        config.session.command
     )
 }
+
+/// Keep what this process and what it execs print as `name` logs in the
+/// user's state directory.
+fn capture_output(name: &str, config: &Config) {
+    let keep = config.log.keep_sessions;
+
+    let home = match nix::unistd::User::from_uid(nix::unistd::getuid()) {
+        Ok(Some(user)) if keep > 0 => user.dir,
+        _ => return,
+    };
+
+    let dir = log::session_dir(&home);
+    if let Err(e) = log::capture(&dir, name, keep) {
+        log::warning!("Couldn't keep the {name} output in {}: {e}", dir.display());
+    }
+}
+
+/// The xwayland supervisor of a session, stopped with the xwayland-satellite
+/// it runs when dropped. Exec'ing the session keeps it running, it then goes
+/// away with the compositor's socket.
+struct Xwayland {
+    supervisor: Child,
+    timeout: Duration,
+}
+
+impl Drop for Xwayland {
+    fn drop(&mut self) {
+        let stopped = self
+            .supervisor
+            .stop(self.timeout)
+            .and_then(|_| self.supervisor.stop_scope(self.timeout));
+
+        if let Err(e) = stopped {
+            log::warning!("Couldn't stop the xwayland supervisor: {e}");
+        }
+    }
+}
+
+/// Start supervising xwayland-satellite on a free display, which this
+/// process and so the session it becomes gets as DISPLAY.
+///
+/// DISPLAY is set before anything listens on it: xwayland-satellite only
+/// starts once the compositor this process starts or becomes made its
+/// socket. X clients the compositor starts right away may not find it yet.
+fn start_xwayland(config: &Config) -> Result<Xwayland> {
+    let display = xwayland::free_display(Path::new(xwayland::TMP));
+    let runtime_dir =
+        std::env::var_os("XDG_RUNTIME_DIR").ok_or(Error::MissingEnv("XDG_RUNTIME_DIR"))?;
+
+    // Taken before the compositor starts, so that its socket is the new one.
+    let existing = xwayland::wayland_sockets(Path::new(&runtime_dir));
+
+    let supervisor = rilm(
+        ["start", "xwayland", "--display", &display.to_string()]
+            .into_iter()
+            .map(String::from)
+            .chain(
+                existing
+                    .into_iter()
+                    .flat_map(|socket| [String::from("--existing"), socket]),
+            )
+            .collect(),
+    )?
+    .isolate()
+    .spawn()?;
+
+    // SAFETY: rilm is single threaded here.
+    unsafe { std::env::set_var("DISPLAY", format!(":{display}")) };
+
+    Ok(Xwayland {
+        supervisor,
+        timeout: Duration::from_secs(config.session.stop_timeout),
+    })
+}
+
+pub fn start_xwayland_supervisor(
+    display: u32,
+    existing: Vec<String>,
+    config: &Config,
+) -> Result<()> {
+    xwayland::supervise(
+        &config.programs.xwayland_satellite,
+        display,
+        &existing,
+        config.xwayland.max_restarts,
+    )
+}
+
+/// Replace this process with a session picked from its desktop file. X11
+/// sessions get their own X server through startx.
+fn exec_session(mut command: Vec<String>, config: &Config) -> Result<()> {
+    if std::env::var("XDG_SESSION_TYPE").is_ok_and(|kind| kind == "x11") {
+        let program =
+            sessions::find_program(&command[0]).ok_or(Error::UnknownProgram(command[0].clone()))?;
+
+        command[0] = program.to_str().ok_or(Error::ToStrError)?.to_string();
+        command.insert(
+            0,
+            config
+                .programs
+                .startx
+                .to_str()
+                .ok_or(Error::ToStrError)?
+                .to_string(),
+        );
+    }
+
+    Err(spawn::Spec::new(&command[0]).args(&command[1..]).exec())
+}
@@ -0,0 +1,440 @@
+use std::{fmt, time::Duration};
+
+use nix::sys::wait::WaitStatus;
+
+use crate::{log, notify};
+
+use super::Result;
+
+pub const GREETER_MAX_FAILURES: u32 = 3;
+/// How long to wait before the graphical greeter is tried again after the
+/// console failed too.
+pub const CONSOLE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// What the greeter step ended with.
+pub enum Greeted<L> {
+    Authenticated(L),
+    Exited(WaitStatus),
+    /// Stopped for a signal, handled before the next step.
+    Interrupted,
+}
+
+/// The part of the display loop that differs between a real seat and a
+/// nested window: how the greeter and the user's session are run.
+pub trait Backend {
+    type Login;
+    type Session;
+
+    /// Whether a text-mode greeter can take over when the graphical one keeps
+    /// failing. Without one, the display stops when the greeter exits.
+    const HAS_CONSOLE: bool;
+
+    /// Checked before every step, so that pending signals are handled there.
+    /// `true` once the display was asked to stop.
+    fn stopping(&mut self) -> Result<bool>;
+
+    /// The login to open without asking, `None` to show the greeter.
+    fn autologin(&mut self) -> Result<Option<Self::Login>>;
+
+    fn greeter(&mut self) -> Result<Greeted<Self::Login>>;
+
+    /// `None` if the user asked for the graphical greeter again.
+    fn console(&mut self) -> Result<Option<Self::Login>>;
+
+    /// Wait `delay` before trying again, less if the display is asked to
+    /// stop meanwhile.
+    fn back_off(&mut self, delay: Duration) {
+        std::thread::sleep(delay);
+    }
+
+    fn open_session(&mut self, login: &mut Self::Login) -> Result<()>;
+
+    fn spawn_session(&mut self, login: &Self::Login) -> Result<Self::Session>;
+
+    fn wait_session(&mut self, session: Self::Session) -> Result<WaitStatus>;
+
+    fn close_session(&mut self, login: Self::Login) -> Result<()>;
+}
+
+pub enum State<L> {
+    Autologin,
+    Greeter { failures: u32 },
+    Console,
+    Authenticated(L),
+    Opened(L),
+    Ended(L),
+    Stopped,
+}
+
+impl<L> fmt::Display for State<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Autologin => write!(f, "autologin"),
+            Self::Greeter { failures: 0 } => write!(f, "greeter"),
+            Self::Greeter { failures } => write!(f, "greeter (after {failures} failures)"),
+            Self::Console => write!(f, "console"),
+            Self::Authenticated(_) => write!(f, "authenticated"),
+            Self::Opened(_) => write!(f, "session opened"),
+            Self::Ended(_) => write!(f, "session ended"),
+            Self::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+/// Run the display loop until the backend can't show a greeter anymore.
+pub fn run<B: Backend>(backend: &mut B) -> Result<()> {
+    let mut state = State::Autologin;
+
+    while !matches!(state, State::Stopped) {
+        let from = state.to_string();
+        state = step(backend, state)?;
+        log::set(log::STATE, Some(&state.to_string()));
+        notify::status(&state.to_string());
+        log::info!("Display: {from} -> {state}");
+    }
+
+    Ok(())
+}
+
+/// Do what `state` asks for and return the state that follows. Once the
+/// backend is stopping, an opened session is closed and nothing else starts.
+pub fn step<B: Backend>(backend: &mut B, state: State<B::Login>) -> Result<State<B::Login>> {
+    if backend.stopping()? {
+        return Ok(match state {
+            State::Opened(login) => State::Ended(login),
+            State::Ended(login) => {
+                if let Err(e) = backend.close_session(login) {
+                    log::error!("Failed to close the session: {e}");
+                }
+
+                State::Stopped
+            }
+            _ => State::Stopped,
+        });
+    }
+
+    Ok(match state {
+        State::Autologin => match backend.autologin() {
+            Ok(Some(login)) => State::Authenticated(login),
+            Ok(None) => State::Greeter { failures: 0 },
+            Err(e) => {
+                log::error!("Autologin failed: {e}");
+                State::Greeter { failures: 0 }
+            }
+        },
+        State::Greeter { failures } => match backend.greeter() {
+            Ok(Greeted::Authenticated(login)) => State::Authenticated(login),
+            Ok(Greeted::Exited(status)) if B::HAS_CONSOLE => {
+                log::error!(
+                    "Greeter exited with {status:?} ({}/{GREETER_MAX_FAILURES})",
+                    failures + 1
+                );
+                greeter_failed(failures)
+            }
+            Ok(Greeted::Exited(status)) => {
+                log::info!("Greeter exited with {status:?}");
+                State::Stopped
+            }
+            Ok(Greeted::Interrupted) => State::Greeter { failures },
+            Err(e) if B::HAS_CONSOLE => {
+                log::error!("Greeter failed: {e}");
+                greeter_failed(failures)
+            }
+            Err(e) => return Err(e),
+        },
+        State::Console => match backend.console() {
+            Ok(Some(login)) => State::Authenticated(login),
+            Ok(None) => State::Greeter { failures: 0 },
+            Err(e) => {
+                log::error!("Console greeter failed: {e}");
+                backend.back_off(CONSOLE_RETRY_DELAY);
+                State::Greeter { failures: 0 }
+            }
+        },
+        State::Authenticated(mut login) => match backend.open_session(&mut login) {
+            Ok(()) => State::Opened(login),
+            Err(e) => {
+                log::error!("Failed to open the session: {e}");
+                State::Greeter { failures: 0 }
+            }
+        },
+        State::Opened(login) => {
+            match backend
+                .spawn_session(&login)
+                .and_then(|session| backend.wait_session(session))
+            {
+                Ok(status) => log::info!("Session ended with {status:?}"),
+                Err(e) => log::error!("Session failed: {e}"),
+            }
+
+            State::Ended(login)
+        }
+        State::Ended(login) => {
+            if let Err(e) = backend.close_session(login) {
+                log::error!("Failed to close the session: {e}");
+            }
+
+            State::Autologin
+        }
+        State::Stopped => State::Stopped,
+    })
+}
+
+fn greeter_failed<L>(failures: u32) -> State<L> {
+    match failures + 1 >= GREETER_MAX_FAILURES {
+        true => State::Console,
+        false => State::Greeter {
+            failures: failures + 1,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use nix::unistd::Pid;
+
+    use super::*;
+    use crate::Error;
+
+    const EXITED: WaitStatus = WaitStatus::Exited(Pid::from_raw(1), 1);
+
+    /// Replays scripted greeter and console results and records every call.
+    #[derive(Default)]
+    struct Script<const CONSOLE: bool> {
+        autologins: VecDeque<Option<&'static str>>,
+        greeters: VecDeque<Result<Greeted<&'static str>>>,
+        consoles: VecDeque<Result<Option<&'static str>>>,
+        fail_open: bool,
+        stopping: bool,
+        calls: Vec<String>,
+    }
+
+    impl<const CONSOLE: bool> Backend for Script<CONSOLE> {
+        type Login = &'static str;
+        type Session = &'static str;
+
+        const HAS_CONSOLE: bool = CONSOLE;
+
+        fn stopping(&mut self) -> Result<bool> {
+            Ok(self.stopping)
+        }
+
+        fn autologin(&mut self) -> Result<Option<&'static str>> {
+            self.calls.push(String::from("autologin"));
+            Ok(self.autologins.pop_front().flatten())
+        }
+
+        fn greeter(&mut self) -> Result<Greeted<&'static str>> {
+            self.calls.push(String::from("greeter"));
+            self.greeters
+                .pop_front()
+                .unwrap_or(Ok(Greeted::Exited(EXITED)))
+        }
+
+        fn console(&mut self) -> Result<Option<&'static str>> {
+            self.calls.push(String::from("console"));
+            self.consoles.pop_front().unwrap_or(Ok(None))
+        }
+
+        fn back_off(&mut self, delay: Duration) {
+            self.calls.push(format!("back off {}s", delay.as_secs()));
+        }
+
+        fn open_session(&mut self, login: &mut &'static str) -> Result<()> {
+            self.calls.push(format!("open {login}"));
+            match self.fail_open {
+                true => Err(Error::Protocol(String::from("refused"))),
+                false => Ok(()),
+            }
+        }
+
+        fn spawn_session(&mut self, login: &&'static str) -> Result<&'static str> {
+            self.calls.push(format!("spawn {login}"));
+            Ok(login)
+        }
+
+        fn wait_session(&mut self, session: &'static str) -> Result<WaitStatus> {
+            self.calls.push(format!("wait {session}"));
+            Ok(WaitStatus::Exited(Pid::from_raw(2), 0))
+        }
+
+        fn close_session(&mut self, login: &'static str) -> Result<()> {
+            self.calls.push(format!("close {login}"));
+            Ok(())
+        }
+    }
+
+    fn steps<B: Backend>(backend: &mut B, mut state: State<B::Login>, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|_| {
+                state = step(backend, std::mem::replace(&mut state, State::Stopped)).unwrap();
+                state.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn session_returns_to_greeter() {
+        let mut backend = Script::<true> {
+            greeters: VecDeque::from([Ok(Greeted::Authenticated("alice"))]),
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Greeter { failures: 0 }, 4);
+
+        assert_eq!(
+            states,
+            [
+                "authenticated",
+                "session opened",
+                "session ended",
+                "autologin"
+            ]
+        );
+        assert_eq!(
+            backend.calls,
+            [
+                "greeter",
+                "open alice",
+                "spawn alice",
+                "wait alice",
+                "close alice"
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_failures_fall_back_to_console() {
+        let mut backend = Script::<true> {
+            greeters: VecDeque::from([
+                Ok(Greeted::Exited(EXITED)),
+                Err(Error::Protocol(String::from("crashed"))),
+                Ok(Greeted::Exited(EXITED)),
+            ]),
+            consoles: VecDeque::from([Ok(None), Ok(Some("bob"))]),
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Greeter { failures: 0 }, 6);
+
+        assert_eq!(
+            states,
+            [
+                "greeter (after 1 failures)",
+                "greeter (after 2 failures)",
+                "console",
+                "greeter",
+                "greeter (after 1 failures)",
+                "greeter (after 2 failures)",
+            ]
+        );
+
+        let states = steps(&mut backend, State::Console, 2);
+        assert_eq!(states, ["authenticated", "session opened"]);
+    }
+
+    #[test]
+    fn console_failure_goes_back_to_greeter() {
+        let mut backend = Script::<true> {
+            consoles: VecDeque::from([Err(Error::Protocol(String::from("no VT")))]),
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Console, 1);
+
+        assert_eq!(states, ["greeter"]);
+        assert_eq!(backend.calls, ["console", "back off 5s"]);
+    }
+
+    #[test]
+    fn failed_open_goes_back_to_greeter() {
+        let mut backend = Script::<true> {
+            fail_open: true,
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Authenticated("carol"), 1);
+
+        assert_eq!(states, ["greeter"]);
+        assert_eq!(backend.calls, ["open carol"]);
+    }
+
+    #[test]
+    fn without_console_greeter_exit_stops() {
+        let mut backend = Script::<false> {
+            greeters: VecDeque::from([Ok(Greeted::Authenticated("dave"))]),
+            ..Default::default()
+        };
+
+        run(&mut backend).unwrap();
+
+        assert_eq!(
+            backend.calls,
+            [
+                "autologin",
+                "greeter",
+                "open dave",
+                "spawn dave",
+                "wait dave",
+                "close dave",
+                "autologin",
+                "greeter"
+            ]
+        );
+    }
+
+    #[test]
+    fn autologin_skips_greeter_until_declined() {
+        let mut backend = Script::<true> {
+            autologins: VecDeque::from([Some("kiosk"), None]),
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Autologin, 5);
+
+        assert_eq!(
+            states,
+            [
+                "authenticated",
+                "session opened",
+                "session ended",
+                "autologin",
+                "greeter"
+            ]
+        );
+        assert_eq!(backend.calls[..2], ["autologin", "open kiosk"]);
+    }
+
+    #[test]
+    fn interrupted_greeter_is_shown_again() {
+        let mut backend = Script::<false> {
+            greeters: VecDeque::from([
+                Ok(Greeted::Interrupted),
+                Ok(Greeted::Authenticated("frank")),
+            ]),
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Greeter { failures: 1 }, 2);
+
+        assert_eq!(states, ["greeter (after 1 failures)", "authenticated"]);
+    }
+
+    #[test]
+    fn stopping_closes_the_opened_session() {
+        let mut backend = Script::<true> {
+            stopping: true,
+            ..Default::default()
+        };
+
+        let states = steps(&mut backend, State::Opened("erin"), 2);
+
+        assert_eq!(states, ["session ended", "stopped"]);
+        assert_eq!(backend.calls, ["close erin"]);
+
+        let states = steps(&mut backend, State::Greeter { failures: 0 }, 1);
+        assert_eq!(states, ["stopped"]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::log;
+
+use super::{Error, Result};
+
+/// Installed by `patch-config`, binds graphical-session.target for the
+/// length of a session since that one can't be started by hand.
+pub const SESSION_TARGET: &str = "rilm-session.target";
+pub const SESSION_TARGET_PATH: &str = "/usr/lib/systemd/user/rilm-session.target";
+
+pub const SESSION_TARGET_UNIT: &str = r#"[Unit]
+Description=rilm session
+Documentation=https://github.com/Hennzau/niri-de
+BindsTo=graphical-session.target
+Wants=graphical-session-pre.target
+After=graphical-session-pre.target
+"#;
+
+/// niri's own units, as niri-session uses them.
+pub const NIRI_SERVICE: &str = "niri.service";
+pub const NIRI_SHUTDOWN_TARGET: &str = "niri-shutdown.target";
+
+/// What user services get from the session, when set.
+pub const SESSION_VARS: [&str; 8] = [
+    "XDG_SESSION_ID",
+    "XDG_SESSION_TYPE",
+    "XDG_SESSION_CLASS",
+    "XDG_SESSION_DESKTOP",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_SEAT",
+    "XDG_VTNR",
+    "DISPLAY",
+];
+
+/// What the compositor exports while it runs, removed again on logout.
+pub const COMPOSITOR_VARS: [&str; 2] = ["WAYLAND_DISPLAY", "NIRI_SOCKET"];
+
+/// The calling user's systemd instance, through `systemctl --user`.
+pub struct UserManager {
+    systemctl: PathBuf,
+}
+
+impl UserManager {
+    pub fn new(systemctl: &Path) -> Self {
+        Self {
+            systemctl: systemctl.to_path_buf(),
+        }
+    }
+
+    /// Copy `keys` from this process' environment into the manager's.
+    pub fn import_environment(&self, keys: &[&str]) -> Result<()> {
+        self.systemctl(["import-environment"].iter().chain(keys))
+    }
+
+    /// Set `KEY=VALUE` variables in the manager's environment.
+    pub fn set_environment(&self, vars: &[String]) -> Result<()> {
+        self.systemctl(
+            [String::from("set-environment")]
+                .iter()
+                .chain(vars)
+                .map(String::as_str),
+        )
+    }
+
+    pub fn unset_environment(&self, keys: &[&str]) -> Result<()> {
+        self.systemctl(["unset-environment"].iter().chain(keys))
+    }
+
+    pub fn start(&self, unit: &str) -> Result<()> {
+        self.systemctl(["start", unit])
+    }
+
+    /// Start `unit` and return once it stopped again.
+    pub fn run(&self, unit: &str) -> Result<()> {
+        self.systemctl(["--wait", "start", unit])
+    }
+
+    /// Start `unit`, stopping everything that conflicts with it.
+    pub fn start_replacing(&self, unit: &str) -> Result<()> {
+        self.systemctl(["start", "--job-mode=replace-irreversibly", unit])
+    }
+
+    pub fn stop(&self, unit: &str) -> Result<()> {
+        self.systemctl(["stop", unit])
+    }
+
+    fn systemctl<S: AsRef<str>>(&self, args: impl IntoIterator<Item = S>) -> Result<()> {
+        let args = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_string())
+            .collect::<Vec<_>>();
+
+        log::debug!("{} --user {}", self.systemctl.display(), args.join(" "));
+
+        let output = Command::new(&self.systemctl)
+            .arg("--user")
+            .args(&args)
+            .output()
+            .map_err(|e| Error::Systemd(format!("{}: {e}", self.systemctl.display())))?;
+
+        if !output.status.success() {
+            return Err(Error::Systemd(format!(
+                "{} {} failed: {}",
+                self.systemctl.display(),
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The [`SESSION_VARS`] this process has.
+pub fn session_vars() -> Vec<&'static str> {
+    SESSION_VARS
+        .into_iter()
+        .filter(|key| std::env::var_os(key).is_some())
+        .collect()
+}
+
+/// Run `session` with [`SESSION_TARGET`] active, so that user services
+/// wanted by graphical-session.target come up along with it.
+pub fn with_target<T>(
+    manager: &UserManager,
+    keys: &[&str],
+    session: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    manager.import_environment(keys)?;
+    manager.start(SESSION_TARGET)?;
+
+    let result = session();
+
+    if let Err(e) = manager.stop(SESSION_TARGET) {
+        log::warning!("{e}");
+    }
+    unset(manager, keys);
+
+    result
+}
+
+/// Run niri as [`NIRI_SERVICE`] with `vars` set for it, the way niri-session
+/// does, until it exits.
+pub fn as_service(manager: &UserManager, keys: &[&str], vars: &[String]) -> Result<()> {
+    manager.import_environment(keys)?;
+    manager.set_environment(vars)?;
+
+    let result = manager.run(NIRI_SERVICE);
+
+    if let Err(e) = manager.start_replacing(NIRI_SHUTDOWN_TARGET) {
+        log::warning!("{e}");
+    }
+
+    let set = vars
+        .iter()
+        .filter_map(|var| Some(var.split_once('=')?.0))
+        .collect::<Vec<_>>();
+    unset(manager, &[keys, &set].concat());
+
+    result
+}
+
+fn unset(manager: &UserManager, keys: &[&str]) {
+    if let Err(e) = manager.unset_environment(&[keys, &COMPOSITOR_VARS].concat()) {
+        log::warning!("{e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    /// A systemctl that logs its arguments, failing for `fail`.
+    fn stub(dir: &Path, fail: &str) -> (UserManager, PathBuf) {
+        let log = dir.join("log");
+        let systemctl = dir.join("systemctl");
+
+        std::fs::write(
+            &systemctl,
+            format!(
+                "#!/bin/sh\necho \"$*\" >> {}\ncase \"$*\" in *{fail}*) exit 1;; esac\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&systemctl, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        (UserManager::new(&systemctl), log)
+    }
+
+    fn calls(log: &Path) -> Vec<String> {
+        std::fs::read_to_string(log)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn target_is_active_for_the_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, log) = stub(dir.path(), "never");
+
+        let status = with_target(&manager, &["XDG_SESSION_TYPE"], || {
+            std::fs::write(dir.path().join("ran"), "").unwrap();
+            Ok(0)
+        });
+
+        assert_eq!(status.unwrap(), 0);
+        assert!(dir.path().join("ran").exists());
+        assert_eq!(
+            calls(&log),
+            [
+                "--user import-environment XDG_SESSION_TYPE",
+                "--user start rilm-session.target",
+                "--user stop rilm-session.target",
+                "--user unset-environment XDG_SESSION_TYPE WAYLAND_DISPLAY NIRI_SOCKET",
+            ]
+        );
+    }
+
+    #[test]
+    fn service_is_shut_down_even_when_it_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let (manager, log) = stub(dir.path(), "--wait");
+
+        let result = as_service(
+            &manager,
+            &["XDG_SEAT"],
+            &[String::from("NIRI_CONFIG=/tmp/niri.kdl")],
+        );
+
+        assert!(matches!(result, Err(Error::Systemd(_))));
+        assert_eq!(
+            calls(&log),
+            [
+                "--user import-environment XDG_SEAT",
+                "--user set-environment NIRI_CONFIG=/tmp/niri.kdl",
+                "--user --wait start niri.service",
+                "--user start --job-mode=replace-irreversibly niri-shutdown.target",
+                "--user unset-environment XDG_SEAT NIRI_CONFIG WAYLAND_DISPLAY NIRI_SOCKET",
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    fs::DirBuilder,
+    io::{ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use nix::unistd::{Gid, Uid};
+
+use crate::{config, log};
+
+use super::{Error, Result};
+
+const NOLOGIN: &str = "/usr/sbin/nologin";
+const NOLOGIN_SHELLS: [&str; 4] = [
+    "/usr/sbin/nologin",
+    "/sbin/nologin",
+    "/usr/bin/false",
+    "/bin/false",
+];
+const DEFAULT_SYS_UID_MAX: u32 = 999;
+const SYSUSERS: &str = "/usr/bin/systemd-sysusers";
+/// Where the greeter's home has to be, so that rilm never takes over a
+/// directory that isn't its own.
+const STATE_DIR: &str = "/var/lib";
+
+/// The system account a process of rilm runs under.
+pub struct Account {
+    pub name: String,
+    pub groups: Vec<String>,
+    pub home: PathBuf,
+}
+
+impl Account {
+    pub fn greeter(config: &config::Greeter) -> Self {
+        Self {
+            name: config.user.clone(),
+            groups: config.groups.clone(),
+            home: config.home.clone(),
+        }
+    }
+
+    /// The sysusers.d(5) lines that create this account.
+    pub fn sysusers(&self) -> String {
+        let mut conf = format!(
+            "u {} - \"RILM greeter\" {} {NOLOGIN}\n",
+            self.name,
+            self.home.display()
+        );
+
+        for group in &self.groups {
+            conf.push_str(&format!("m {} {group}\n", self.name));
+        }
+
+        conf
+    }
+}
+
+/// What /etc says about an account.
+#[derive(Debug)]
+pub struct Entry {
+    pub uid: Uid,
+    pub gid: Gid,
+    pub home: PathBuf,
+    pub shell: String,
+    pub password: Option<String>,
+    pub groups: Vec<String>,
+}
+
+/// Make sure `account` exists as configured, creating it with
+/// systemd-sysusers if needed, and refuse to go on if it looks like
+/// someone could log into it.
+pub fn ensure(account: &Account, root: &Path) -> Result<Entry> {
+    let entry = match lookup(account, root)? {
+        Some(entry) if missing_groups(account, &entry).is_empty() => entry,
+        _ => {
+            log::info!("Provisioning the {} account", account.name);
+            provision(account, root)?;
+
+            lookup(account, root)?.ok_or(Error::UnknownUserWithName(account.name.clone()))?
+        }
+    };
+
+    check(account, &entry, sys_uid_max(root))?;
+    ensure_home(account, &entry, root)?;
+
+    Ok(entry)
+}
+
+pub fn lookup(account: &Account, root: &Path) -> Result<Option<Entry>> {
+    let etc = root.join("etc");
+
+    let Some(passwd) = read_table(&etc.join("passwd"))?
+        .into_iter()
+        .find(|fields| fields[0] == account.name)
+    else {
+        return Ok(None);
+    };
+
+    let [_, _, uid, gid, _, home, shell, ..] = passwd.as_slice() else {
+        return Err(Error::Account(format!(
+            "malformed passwd entry for {}",
+            account.name
+        )));
+    };
+
+    let password = read_table(&etc.join("shadow"))?
+        .into_iter()
+        .find(|fields| fields[0] == account.name)
+        .and_then(|fields| fields.get(1).cloned());
+
+    let groups = read_table(&etc.join("group"))?
+        .into_iter()
+        .filter(|fields| {
+            fields
+                .get(3)
+                .is_some_and(|members| members.split(',').any(|m| m == account.name))
+                || fields.get(2) == Some(gid)
+        })
+        .map(|fields| fields[0].clone())
+        .collect();
+
+    let parse = |id: &String| {
+        id.parse::<u32>()
+            .map_err(|_| Error::Account(format!("malformed id {id} for {}", account.name)))
+    };
+
+    Ok(Some(Entry {
+        uid: Uid::from_raw(parse(uid)?),
+        gid: Gid::from_raw(parse(gid)?),
+        home: PathBuf::from(home),
+        shell: shell.clone(),
+        password,
+        groups,
+    }))
+}
+
+/// Everything that would let the account be used for anything else than
+/// running the greeter.
+pub fn check(account: &Account, entry: &Entry, sys_uid_max: u32) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if entry.uid.is_root() || entry.uid.as_raw() > sys_uid_max {
+        problems.push(format!("uid {} is not a system uid", entry.uid));
+    }
+
+    if !NOLOGIN_SHELLS.contains(&entry.shell.as_str()) {
+        problems.push(format!("it has the login shell {}", entry.shell));
+    }
+
+    match entry.password.as_deref() {
+        Some(hash) if hash.starts_with('!') || hash.starts_with('*') => {}
+        None => {}
+        Some(_) => problems.push(String::from("it has a password")),
+    }
+
+    let missing = missing_groups(account, entry);
+    if !missing.is_empty() {
+        problems.push(format!("it is not in {}", missing.join(", ")));
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(Error::Account(format!(
+            "refusing to use the {} account: {}",
+            account.name,
+            problems.join(", ")
+        ))),
+    }
+}
+
+fn missing_groups<'a>(account: &'a Account, entry: &Entry) -> Vec<&'a str> {
+    account
+        .groups
+        .iter()
+        .filter(|group| !entry.groups.contains(group))
+        .map(String::as_str)
+        .collect()
+}
+
+fn provision(account: &Account, root: &Path) -> Result<()> {
+    let mut sysusers = Command::new(SYSUSERS)
+        .arg(format!("--root={}", root.display()))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = sysusers.stdin.take() {
+        stdin.write_all(account.sysusers().as_bytes())?;
+    }
+
+    match sysusers.wait()? {
+        status if status.success() => Ok(()),
+        status => Err(Error::Account(format!("{SYSUSERS} failed with {status}"))),
+    }
+}
+
+/// The state directory only the account can read. Only the configured one
+/// in [`STATE_DIR`] is created or fixed up, and only if it is missing or
+/// already the account's.
+fn ensure_home(account: &Account, entry: &Entry, root: &Path) -> Result<()> {
+    let refuse = |reason: String| {
+        Err(Error::Account(format!(
+            "refusing to use {} as the home of {}: {reason}",
+            entry.home.display(),
+            account.name
+        )))
+    };
+
+    if entry.home != account.home {
+        return refuse(format!("{} is configured", account.home.display()));
+    }
+
+    let in_state_dir = account.home.strip_prefix(STATE_DIR).is_ok_and(|rest| {
+        rest.components().count() > 0
+            && rest.components().all(|c| matches!(c, Component::Normal(_)))
+    });
+    if !in_state_dir {
+        return refuse(format!("it is not in {STATE_DIR}"));
+    }
+
+    let home = root.join(entry.home.strip_prefix("/").unwrap_or(&entry.home));
+
+    let metadata = match std::fs::symlink_metadata(&home) {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if let Some(parent) = home.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            DirBuilder::new().mode(0o700).create(&home)?;
+            nix::unistd::chown(&home, Some(entry.uid), Some(entry.gid))?;
+
+            return Ok(());
+        }
+        metadata => metadata?,
+    };
+
+    if !metadata.is_dir() {
+        return refuse(String::from("it is not a directory"));
+    }
+    if metadata.uid() != entry.uid.as_raw() {
+        return refuse(format!("it is owned by uid {}", metadata.uid()));
+    }
+
+    if metadata.gid() != entry.gid.as_raw() {
+        nix::unistd::chown(&home, None, Some(entry.gid))?;
+    }
+    if metadata.mode() & 0o777 != 0o700 {
+        std::fs::set_permissions(&home, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(())
+}
+
+fn sys_uid_max(root: &Path) -> u32 {
+    std::fs::read_to_string(root.join("etc/login.defs"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("SYS_UID_MAX"))
+        .find_map(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_SYS_UID_MAX)
+}
+
+fn read_table(path: &Path) -> Result<Vec<Vec<String>>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(contents
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split(':').map(String::from).collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etc(passwd: &str, shadow: &str, group: &str) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let etc = root.path().join("etc");
+        std::fs::create_dir(&etc).unwrap();
+        std::fs::write(etc.join("passwd"), passwd).unwrap();
+        std::fs::write(etc.join("shadow"), shadow).unwrap();
+        std::fs::write(etc.join("group"), group).unwrap();
+        root
+    }
+
+    const GROUPS: &str = "video:x:39:greeter\ninput:x:104:greeter\nrender:x:105:greeter\n";
+
+    #[test]
+    fn locked_system_account_is_accepted() {
+        let root = etc(
+            "root:x:0:0::/root:/bin/bash\ngreeter:x:977:977::/var/lib/rilm-greeter:/usr/sbin/nologin\n",
+            "greeter:!*:19000::::::\n",
+            GROUPS,
+        );
+
+        let account = Account::greeter(&config::Greeter::default());
+        let entry = lookup(&account, root.path()).unwrap().unwrap();
+
+        assert_eq!(entry.uid.as_raw(), 977);
+        assert_eq!(entry.groups, ["video", "input", "render"]);
+        check(&account, &entry, sys_uid_max(root.path())).unwrap();
+    }
+
+    #[test]
+    fn login_shell_and_password_are_refused() {
+        let root = etc(
+            "greeter:x:1001:1001::/home/greeter:/bin/bash\n",
+            "greeter:$6$salt$hash:19000::::::\n",
+            "video:x:39:\n",
+        );
+
+        let account = Account::greeter(&config::Greeter::default());
+        let entry = lookup(&account, root.path()).unwrap().unwrap();
+
+        let Err(Error::Account(reason)) = check(&account, &entry, 999) else {
+            panic!("account should be refused");
+        };
+        assert!(reason.contains("not a system uid"));
+        assert!(reason.contains("login shell /bin/bash"));
+        assert!(reason.contains("has a password"));
+        assert!(reason.contains("not in video, input, render"));
+    }
+
+    fn entry(home: &str, uid: u32) -> Entry {
+        Entry {
+            uid: Uid::from_raw(uid),
+            gid: Gid::current(),
+            home: PathBuf::from(home),
+            shell: String::from(NOLOGIN),
+            password: None,
+            groups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn home_is_created_only_in_the_state_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let account = Account::greeter(&config::Greeter::default());
+        let uid = Uid::current().as_raw();
+
+        ensure_home(&account, &entry("/var/lib/rilm-greeter", uid), root.path()).unwrap();
+
+        let home = root.path().join("var/lib/rilm-greeter");
+        assert_eq!(std::fs::metadata(&home).unwrap().mode() & 0o7777, 0o700);
+
+        for home in ["/", "/nonexistent", "/var/lib", "/var/lib/../../etc"] {
+            let account = Account {
+                home: PathBuf::from(home),
+                ..Account::greeter(&config::Greeter::default())
+            };
+            assert!(ensure_home(&account, &entry(home, uid), root.path()).is_err());
+        }
+
+        // The passwd entry has to agree with the configuration.
+        assert!(ensure_home(&account, &entry("/home/shared", uid), root.path()).is_err());
+    }
+
+    #[test]
+    fn home_of_someone_else_is_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let account = Account::greeter(&config::Greeter::default());
+        let uid = Uid::current().as_raw();
+
+        let home = root.path().join("var/lib/rilm-greeter");
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::set_permissions(&home, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let Err(Error::Account(reason)) = ensure_home(
+            &account,
+            &entry("/var/lib/rilm-greeter", uid + 1),
+            root.path(),
+        ) else {
+            panic!("home should be refused");
+        };
+        assert!(reason.contains(&format!("owned by uid {uid}")));
+        assert_eq!(std::fs::metadata(&home).unwrap().mode() & 0o7777, 0o755);
+
+        std::fs::remove_dir(&home).unwrap();
+        std::os::unix::fs::symlink(root.path(), &home).unwrap();
+        assert!(ensure_home(&account, &entry("/var/lib/rilm-greeter", uid), root.path()).is_err());
+    }
+
+    #[test]
+    fn sysusers_snippet() {
+        assert_eq!(
+            Account::greeter(&config::Greeter::default()).sysusers(),
+            "u greeter - \"RILM greeter\" /var/lib/rilm-greeter /usr/sbin/nologin\n\
+             m greeter video\nm greeter input\nm greeter render\n"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+    process::Command,
+    time::SystemTime,
+};
+
+use nix::{
+    fcntl::{Flock, FlockArg},
+    libc::{self, c_char},
+};
+
+use crate::config;
+
+use super::{Error, Result};
+
+const RECORD_SIZE: usize = size_of::<libc::utmpx>();
+
+/// What `who`, `last` and `lastlog` show for a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// The terminal, without `/dev/`.
+    pub line: String,
+    pub user: String,
+    /// The session leader, whose process id is also its session id.
+    pub pid: i32,
+    pub time: SystemTime,
+}
+
+impl Record {
+    /// `user` is left out of dead records, as login(1) does.
+    fn utmpx(&self, kind: libc::c_short) -> libc::utmpx {
+        // SAFETY: utmpx is plain data, all zeros is an empty record.
+        let mut entry: libc::utmpx = unsafe { std::mem::zeroed() };
+
+        entry.ut_type = kind;
+        entry.ut_pid = self.pid;
+        copy(&mut entry.ut_line, &self.line);
+        copy(&mut entry.ut_id, id(&self.line));
+        if kind == libc::USER_PROCESS {
+            copy(&mut entry.ut_user, &self.user);
+        }
+        entry.ut_session = self.pid as _;
+
+        let since_epoch = self
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        entry.ut_tv.tv_sec = since_epoch.as_secs() as _;
+        entry.ut_tv.tv_usec = since_epoch.subsec_micros() as _;
+
+        entry
+    }
+}
+
+/// Record the start of a session in utmp, wtmp and lastlog2.
+pub fn login(record: &Record, accounting: &config::Accounting, lastlog2: &Path) -> Result<()> {
+    let entry = record.utmpx(libc::USER_PROCESS);
+
+    write_utmp(&accounting.utmp, &entry)?;
+    append_wtmp(&accounting.wtmp, &entry)?;
+
+    // Only systems with lastlog2 have its database.
+    if accounting.lastlog2.exists() {
+        set_lastlog2(lastlog2, &accounting.lastlog2, &record.user)?;
+    }
+
+    Ok(())
+}
+
+/// Record the end of a session in utmp and wtmp.
+pub fn logout(record: &Record, accounting: &config::Accounting) -> Result<()> {
+    let entry = Record {
+        time: SystemTime::now(),
+        ..record.clone()
+    }
+    .utmpx(libc::DEAD_PROCESS);
+
+    write_utmp(&accounting.utmp, &entry)?;
+    append_wtmp(&accounting.wtmp, &entry)
+}
+
+/// Replace the record for the same line, or add one.
+fn write_utmp(path: &Path, entry: &libc::utmpx) -> Result<()> {
+    let mut file = lock(
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o664)
+            .open(path)?,
+    )?;
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+
+    let slot = contents
+        .chunks_exact(RECORD_SIZE)
+        .map(parse)
+        .position(|existing| {
+            existing.ut_id == entry.ut_id
+                && matches!(
+                    existing.ut_type,
+                    libc::INIT_PROCESS
+                        | libc::LOGIN_PROCESS
+                        | libc::USER_PROCESS
+                        | libc::DEAD_PROCESS
+                )
+        })
+        .unwrap_or(contents.len() / RECORD_SIZE);
+
+    file.write_all_at(bytes(entry), (slot * RECORD_SIZE) as u64)?;
+
+    Ok(())
+}
+
+fn append_wtmp(path: &Path, entry: &libc::utmpx) -> Result<()> {
+    let mut file = lock(
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o664)
+            .open(path)?,
+    )?;
+
+    file.write_all(bytes(entry))?;
+
+    Ok(())
+}
+
+fn set_lastlog2(program: &Path, database: &Path, user: &str) -> Result<()> {
+    let output = Command::new(program)
+        .arg("--database")
+        .arg(database)
+        .args(["--user", user, "--set"])
+        .output()
+        .map_err(|e| Error::Accounting(format!("{}: {e}", program.display())))?;
+
+    if !output.status.success() {
+        return Err(Error::Accounting(format!(
+            "{} failed for {user}: {}",
+            program.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+fn lock(file: File) -> Result<Flock<File>> {
+    Flock::lock(file, FlockArg::LockExclusive).map_err(|(_, e)| e.into())
+}
+
+/// The last four characters of `line` without its `tty` prefix, as agetty
+/// and systemd pick it.
+fn id(line: &str) -> &str {
+    let id = line.strip_prefix("tty").unwrap_or(line);
+    let start = id.char_indices().rev().nth(3).map_or(0, |(index, _)| index);
+
+    &id[start..]
+}
+
+/// Truncating, the fields need no terminating NUL.
+fn copy(field: &mut [c_char], value: &str) {
+    for (byte, c) in field.iter_mut().zip(value.bytes()) {
+        *byte = c as c_char;
+    }
+}
+
+fn bytes(entry: &libc::utmpx) -> &[u8] {
+    // SAFETY: utmpx is plain data of RECORD_SIZE bytes.
+    unsafe { std::slice::from_raw_parts((entry as *const libc::utmpx).cast(), RECORD_SIZE) }
+}
+
+fn parse(chunk: &[u8]) -> libc::utmpx {
+    assert_eq!(chunk.len(), RECORD_SIZE);
+    // SAFETY: any bytes make a valid utmpx.
+    unsafe { std::ptr::read_unaligned(chunk.as_ptr().cast()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::ErrorKind, os::unix::fs::PermissionsExt, path::PathBuf};
+
+    use super::*;
+
+    fn field(field: &[c_char]) -> String {
+        field
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as u8 as char)
+            .collect()
+    }
+
+    /// The records of a utmp or wtmp file, a missing one being empty.
+    fn read(path: &Path) -> Result<Vec<libc::utmpx>> {
+        match std::fs::read(path) {
+            Ok(contents) => Ok(contents.chunks_exact(RECORD_SIZE).map(parse).collect()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn accounting(dir: &Path) -> config::Accounting {
+        config::Accounting {
+            enable: true,
+            utmp: dir.join("utmp"),
+            wtmp: dir.join("wtmp"),
+            lastlog2: dir.join("lastlog2.db"),
+        }
+    }
+
+    fn record(line: &str, user: &str, pid: i32) -> Record {
+        Record {
+            line: line.to_string(),
+            user: user.to_string(),
+            pid,
+            time: SystemTime::UNIX_EPOCH + std::time::Duration::from_micros(1_700_000_000_000_042),
+        }
+    }
+
+    #[test]
+    fn picks_ids_like_agetty() {
+        assert_eq!(id("tty2"), "2");
+        assert_eq!(id("tty12"), "12");
+        assert_eq!(id("pts/10"), "s/10");
+    }
+
+    #[test]
+    fn records_sessions_in_utmp_and_wtmp() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounting = accounting(dir.path());
+        let missing = PathBuf::from("/nonexistent/lastlog2");
+
+        login(&record("tty2", "erin", 41), &accounting, &missing).unwrap();
+        login(&record("tty3", "sam", 52), &accounting, &missing).unwrap();
+        logout(&record("tty2", "erin", 41), &accounting).unwrap();
+
+        let utmp = read(&accounting.utmp).unwrap();
+        assert_eq!(utmp.len(), 2);
+
+        assert_eq!(utmp[0].ut_type, libc::DEAD_PROCESS);
+        assert_eq!(utmp[0].ut_pid, 41);
+        assert_eq!(field(&utmp[0].ut_line), "tty2");
+        assert_eq!(field(&utmp[0].ut_id), "2");
+        assert_eq!(field(&utmp[0].ut_user), "");
+
+        assert_eq!(utmp[1].ut_type, libc::USER_PROCESS);
+        assert_eq!(field(&utmp[1].ut_user), "sam");
+        assert_eq!(utmp[1].ut_session, 52);
+        assert_eq!(utmp[1].ut_tv.tv_sec, 1_700_000_000);
+        assert_eq!(utmp[1].ut_tv.tv_usec, 42);
+
+        let wtmp = read(&accounting.wtmp).unwrap();
+        assert_eq!(
+            wtmp.iter()
+                .map(|entry| (entry.ut_type, field(&entry.ut_line)))
+                .collect::<Vec<_>>(),
+            [
+                (libc::USER_PROCESS, String::from("tty2")),
+                (libc::USER_PROCESS, String::from("tty3")),
+                (libc::DEAD_PROCESS, String::from("tty2")),
+            ]
+        );
+    }
+
+    #[test]
+    fn sets_lastlog2_when_its_database_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounting = accounting(dir.path());
+
+        let log = dir.path().join("log");
+        let lastlog2 = dir.path().join("lastlog2");
+        std::fs::write(
+            &lastlog2,
+            format!("#!/bin/sh\necho \"$*\" >> {}\n", log.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&lastlog2, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        login(&record("tty2", "erin", 41), &accounting, &lastlog2).unwrap();
+        assert!(!log.exists());
+
+        std::fs::write(&accounting.lastlog2, "").unwrap();
+        login(&record("tty2", "erin", 41), &accounting, &lastlog2).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            format!(
+                "--database {} --user erin --set\n",
+                accounting.lastlog2.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::{environment::Environment, log, spawn};
+
+use super::{Error, Result};
+
+/// Where X servers keep their sockets and lock files.
+pub const TMP: &str = "/tmp";
+
+/// How long the compositor gets to create its socket.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const RESTART_WINDOW: Duration = Duration::from_secs(60);
+
+/// The lowest display number with neither a socket nor a lock file in `tmp`.
+pub fn free_display(tmp: &Path) -> u32 {
+    (0..)
+        .find(|n| {
+            !tmp.join(format!(".X11-unix/X{n}")).exists()
+                && !tmp.join(format!(".X{n}-lock")).exists()
+        })
+        .unwrap_or_default()
+}
+
+/// The Wayland sockets in `runtime_dir`, lock files left out.
+pub fn wayland_sockets(runtime_dir: &Path) -> Vec<String> {
+    let mut sockets = std::fs::read_dir(runtime_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with("wayland-") && !name.ends_with(".lock"))
+        .collect::<Vec<_>>();
+
+    sockets.sort();
+    sockets
+}
+
+/// Allows `max` restarts within [`RESTART_WINDOW`].
+pub struct Restarts {
+    max: usize,
+    times: VecDeque<Instant>,
+}
+
+impl Restarts {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            times: VecDeque::new(),
+        }
+    }
+
+    /// Whether a crash at `now` may be followed by another start.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        while self
+            .times
+            .front()
+            .is_some_and(|time| now.duration_since(*time) > RESTART_WINDOW)
+        {
+            self.times.pop_front();
+        }
+
+        self.times.push_back(now);
+        self.times.len() <= self.max
+    }
+}
+
+/// Wait for the session's compositor, the first Wayland socket not in
+/// `existing`, then keep `program` serving `display` on it until the
+/// compositor goes away.
+pub fn supervise(
+    program: &Path,
+    display: u32,
+    existing: &[String],
+    max_restarts: usize,
+) -> Result<()> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .ok_or(Error::MissingEnv("XDG_RUNTIME_DIR"))?;
+
+    let deadline = Instant::now() + SOCKET_TIMEOUT;
+    let wayland = loop {
+        if let Some(socket) = wayland_sockets(&runtime_dir)
+            .into_iter()
+            .find(|socket| !existing.contains(socket))
+        {
+            break socket;
+        }
+
+        if Instant::now() > deadline {
+            return Err(Error::Spawn(format!(
+                "{}: no compositor appeared in {}",
+                program.display(),
+                runtime_dir.display()
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    let mut env = Environment::inherited();
+    env.set("WAYLAND_DISPLAY", &wayland);
+    env.set("DISPLAY", format!(":{display}"));
+
+    let spec = spawn::Spec::new(program)
+        .arg(format!(":{display}"))
+        .env(env.cstrings()?);
+
+    let mut restarts = Restarts::new(max_restarts);
+
+    loop {
+        let status = spec.spawn()?.wait()?;
+
+        if !runtime_dir.join(&wayland).exists() {
+            return Ok(());
+        }
+
+        log::warning!("{} exited with {status:?}", program.display());
+        if !restarts.allow(Instant::now()) {
+            log::warning!(
+                "{} crashed {max_restarts} times within a minute, leaving it down",
+                program.display()
+            );
+            return Ok(());
+        }
+
+        std::thread::sleep(RESTART_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_taken_displays() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir(tmp.path().join(".X11-unix")).unwrap();
+
+        assert_eq!(free_display(tmp.path()), 0);
+
+        std::fs::write(tmp.path().join(".X11-unix/X0"), "").unwrap();
+        std::fs::write(tmp.path().join(".X1-lock"), "").unwrap();
+
+        assert_eq!(free_display(tmp.path()), 2);
+    }
+
+    #[test]
+    fn finds_wayland_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["wayland-1", "wayland-1.lock", "wayland-0", "pipewire-0"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        assert_eq!(wayland_sockets(dir.path()), ["wayland-0", "wayland-1"]);
+    }
+
+    #[test]
+    fn gives_up_after_too_many_crashes() {
+        let mut restarts = Restarts::new(2);
+        let start = Instant::now();
+
+        assert!(restarts.allow(start));
+        assert!(restarts.allow(start + Duration::from_secs(1)));
+        assert!(!restarts.allow(start + Duration::from_secs(2)));
+
+        // Old crashes are forgotten.
+        assert!(restarts.allow(start + Duration::from_secs(62)));
+    }
+}